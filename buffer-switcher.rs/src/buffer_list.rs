@@ -1,8 +1,11 @@
+use crate::identity::FileId;
 use crate::pattern::Target;
+use crate::stale::Stale;
 
 use nvim_router::nvim_rs::Value;
 
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, PartialEq)]
 pub(super) struct BufferId(Value);
@@ -47,16 +50,151 @@ impl BufferId {
     }
 }
 
+/// Where a buffer stands relative to the window the switcher was opened from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Focus {
+    /// The current buffer, which is least likely to be switched to.
+    Current,
+    #[default]
+    Other,
+    /// The alternate buffer (`#`), which is most likely to be switched to.
+    Alternate,
+}
+
 #[derive(Debug)]
 pub(super) struct Buffer {
     pub(super) id: BufferId,
+    /// The name Neovim gave the buffer.
+    pub(super) path: String,
+    /// The path with the symlinks resolved, which identifies the file in the history and the
+    /// pins. The same as `path` if it cannot be resolved.
+    pub(super) canonical_path: String,
     pub(super) file: Target,
     pub(super) metadata: Value,
+    /// When the buffer was last used, in seconds since the Unix epoch.
+    pub(super) last_used: u64,
+    pub(super) focus: Focus,
+    pub(super) in_current_tab: bool,
+    /// The root directory of the project the file belongs to.
+    pub(super) project: Option<String>,
+    pub(super) file_id: Option<FileId>,
+    /// When the file was modified as of loading or writing the buffer, in seconds since the Unix
+    /// epoch.
+    pub(super) loaded_mtime: Option<u64>,
+    pub(super) stale: Option<Stale>,
+}
+
+impl Buffer {
+    /// Buffers of the same file have the same key.
+    fn key(&self) -> Key {
+        if let Some(file_id) = self.file_id {
+            Key::File(file_id)
+        } else if self.path.is_empty() {
+            Key::Id(self.id.clone())
+        } else {
+            Key::Path(self.canonical_path.clone())
+        }
+    }
+
+    /// Takes the tabs of `other`, a copy of this buffer in other tabs.
+    fn merge(&mut self, other: Buffer) {
+        if let Value::Array(tabs) = &mut self.metadata
+            && let Value::Array(other_tabs) = other.metadata
+        {
+            tabs.extend(other_tabs);
+        }
+        self.in_current_tab |= other.in_current_tab;
+    }
+
+    /// A buffer of the current tab named `path`, for the tests to set the other fields on.
+    #[cfg(test)]
+    pub(super) fn for_test(id: i64, path: &str) -> Self {
+        let mut file = Target::with_capacity(path.len());
+        file.push_original(path, 0);
+        file.split_basename();
+        Self {
+            id: BufferId::from_id(&Value::from(id)),
+            path: path.to_string(),
+            canonical_path: path.to_string(),
+            file,
+            metadata: Value::Nil,
+            last_used: 0,
+            focus: Focus::Other,
+            in_current_tab: true,
+            project: None,
+            file_id: None,
+            loaded_mtime: None,
+            stale: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    File(FileId),
+    Path(String),
+    Id(BufferId),
+}
+
+/// Which copy is kept when a buffer open in several tabs is collapsed into one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TabPreference {
+    /// The copy in the current tab, so that the buffer stays in the current tab section.
+    #[default]
+    Current,
+    /// A copy in another tab, to jump to the tab where it is already open.
+    Other,
+}
+
+impl TabPreference {
+    pub(super) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "current" => Some(Self::Current),
+            "other" => Some(Self::Other),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub(super) struct BufferList(Vec<Buffer>);
 
+/// Collapses each buffer open in several tabs into one item, whose metadata lists every tab
+/// containing it with the preferred one first.
+pub(super) fn dedupe(
+    current_tab: &mut BufferList,
+    other_tabs: &mut BufferList,
+    prefer: TabPreference,
+) {
+    let (preferred, rest) = match prefer {
+        TabPreference::Current => (current_tab, other_tabs),
+        TabPreference::Other => (other_tabs, current_tab),
+    };
+
+    let mut kept: [Vec<Buffer>; 2] = [Vec::new(), Vec::new()];
+    let mut seen: BTreeMap<Key, (usize, usize)> = BTreeMap::new();
+    for (list, buffers) in [
+        std::mem::take(&mut preferred.0),
+        std::mem::take(&mut rest.0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        for buf in buffers {
+            if let Some(&(kept_list, i)) = seen.get(&buf.key()) {
+                kept[kept_list][i].merge(buf);
+            } else {
+                seen.insert(buf.key(), (list, kept[list].len()));
+                kept[list].push(buf);
+            }
+        }
+    }
+
+    let [preferred_kept, rest_kept] = kept;
+    preferred.0 = preferred_kept;
+    rest.0 = rest_kept;
+}
+
 impl<'a> IntoIterator for &'a BufferList {
     type Item = &'a Buffer;
     type IntoIter = std::slice::Iter<'a, Buffer>;
@@ -66,8 +204,87 @@ impl<'a> IntoIterator for &'a BufferList {
     }
 }
 
+impl<'a> IntoIterator for &'a mut BufferList {
+    type Item = &'a mut Buffer;
+    type IntoIter = std::slice::IterMut<'a, Buffer>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter_mut()
+    }
+}
+
 impl FromIterator<Buffer> for BufferList {
     fn from_iter<T: IntoIterator<Item = Buffer>>(iter: T) -> Self {
         Self(<_ as FromIterator<_>>::from_iter(iter))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(id: i64, path: &str, tab: i64, in_current_tab: bool) -> Buffer {
+        Buffer {
+            metadata: Value::Array(vec![Value::from(tab)]),
+            in_current_tab,
+            ..Buffer::for_test(id, path)
+        }
+    }
+
+    fn lists() -> (BufferList, BufferList) {
+        let current_tab = [buffer(1, "/a", 1, true), buffer(2, "", 1, true)]
+            .into_iter()
+            .collect();
+        let other_tabs = [
+            buffer(1, "/a", 2, false),
+            buffer(3, "/b", 2, false),
+            buffer(2, "", 3, false),
+            buffer(3, "/b", 3, false),
+        ]
+        .into_iter()
+        .collect();
+        (current_tab, other_tabs)
+    }
+
+    fn summary(list: &BufferList) -> Vec<(String, Value, bool)> {
+        list.into_iter()
+            .map(|buf| (buf.path.clone(), buf.metadata.clone(), buf.in_current_tab))
+            .collect()
+    }
+
+    fn tabs(tabs: &[i64]) -> Value {
+        Value::Array(tabs.iter().map(|&tab| Value::from(tab)).collect())
+    }
+
+    #[test]
+    fn dedupe_prefer_current() {
+        let (mut current_tab, mut other_tabs) = lists();
+        dedupe(&mut current_tab, &mut other_tabs, TabPreference::Current);
+        assert_eq!(
+            summary(&current_tab),
+            [
+                ("/a".to_string(), tabs(&[1, 2]), true),
+                (String::new(), tabs(&[1, 3]), true),
+            ]
+        );
+        assert_eq!(
+            summary(&other_tabs),
+            [("/b".to_string(), tabs(&[2, 3]), false)]
+        );
+    }
+
+    #[test]
+    fn dedupe_prefer_other() {
+        let (mut current_tab, mut other_tabs) = lists();
+        dedupe(&mut current_tab, &mut other_tabs, TabPreference::Other);
+        assert_eq!(summary(&current_tab), []);
+        assert_eq!(
+            summary(&other_tabs),
+            [
+                ("/a".to_string(), tabs(&[2, 1]), true),
+                ("/b".to_string(), tabs(&[2, 3]), false),
+                (String::new(), tabs(&[3, 1]), true),
+            ]
+        );
+    }
+}
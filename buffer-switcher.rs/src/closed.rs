@@ -0,0 +1,110 @@
+use nvim_router::nvim_rs::Value;
+
+use std::collections::{HashMap, VecDeque};
+
+/// The number of closed buffers remembered in each tab.
+const LIMIT: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Closed {
+    /// The name Neovim gave the buffer.
+    pub(super) path: String,
+    pub(super) canonical_path: String,
+    /// The id the buffer had, which stays valid unless the buffer has been wiped out.
+    pub(super) buf_id: Value,
+    /// When the buffer was closed, in seconds since the Unix epoch.
+    pub(super) closed_at: u64,
+}
+
+/// The buffers recently closed in each tab, the most recent first.
+#[derive(Debug, Default)]
+pub(super) struct RecentlyClosed {
+    tabs: HashMap<i64, VecDeque<Closed>>,
+}
+
+impl RecentlyClosed {
+    /// Remembers the buffer as closed in the tab, forgetting the oldest one beyond the limit.
+    pub(super) fn push(&mut self, tab: i64, closed: Closed) {
+        let closed_in_tab = self.tabs.entry(tab).or_default();
+        closed_in_tab.retain(|other| other.path != closed.path);
+        closed_in_tab.push_front(closed);
+        closed_in_tab.truncate(LIMIT);
+    }
+
+    pub(super) fn remove_tab(&mut self, tab: i64) {
+        self.tabs.remove(&tab);
+    }
+
+    pub(super) fn get(&self, tab: i64) -> impl Iterator<Item = &Closed> {
+        self.tabs.get(&tab).into_iter().flatten()
+    }
+
+    /// Takes the most recently closed buffer of the tab that `reopen` accepts.
+    pub(super) fn take_last(
+        &mut self,
+        tab: i64,
+        mut reopen: impl FnMut(&Closed) -> bool,
+    ) -> Option<Closed> {
+        let closed_in_tab = self.tabs.get_mut(&tab)?;
+        let pos = closed_in_tab.iter().position(&mut reopen)?;
+        let closed = closed_in_tab.remove(pos);
+        if closed_in_tab.is_empty() {
+            self.tabs.remove(&tab);
+        }
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closed(path: &str, closed_at: u64) -> Closed {
+        Closed {
+            path: path.to_string(),
+            canonical_path: path.to_string(),
+            buf_id: Value::from(closed_at),
+            closed_at,
+        }
+    }
+
+    fn paths(recently_closed: &RecentlyClosed, tab: i64) -> Vec<&str> {
+        recently_closed
+            .get(tab)
+            .map(|closed| closed.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn bounded() {
+        let mut recently_closed = RecentlyClosed::default();
+        for i in 0..LIMIT as u64 + 5 {
+            recently_closed.push(1, closed(&format!("/{i}"), i));
+        }
+        recently_closed.push(1, closed("/10", 100));
+        recently_closed.push(2, closed("/a", 1));
+
+        let closed_in_tab = paths(&recently_closed, 1);
+        assert_eq!(closed_in_tab.len(), LIMIT);
+        assert_eq!(closed_in_tab[..3], ["/10", "/24", "/23"]);
+        assert!(closed_in_tab.contains(&"/5"));
+        assert!(!closed_in_tab.contains(&"/4"));
+        assert_eq!(paths(&recently_closed, 2), ["/a"]);
+    }
+
+    #[test]
+    fn take_last() {
+        let mut recently_closed = RecentlyClosed::default();
+        recently_closed.push(1, closed("/a", 1));
+        recently_closed.push(1, closed("/b", 2));
+
+        let last = recently_closed.take_last(1, |closed| closed.path != "/b");
+        assert_eq!(last, Some(closed("/a", 1)));
+        assert_eq!(
+            recently_closed.take_last(1, |_| true),
+            Some(closed("/b", 2))
+        );
+        assert_eq!(recently_closed.take_last(1, |_| true), None);
+        assert!(recently_closed.tabs.is_empty());
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::fs::{self, TryLockError};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Files and queries not used for this long are forgotten.
+const MAX_AGE: u64 = 90 * DAY;
+/// The number of files remembered, the most recently visited ones.
+const MAX_ENTRIES: usize = 2000;
+/// The number of queries whose selections are remembered, the most recently used ones.
+const MAX_QUERIES: usize = 500;
+
+/// Seconds since the Unix epoch.
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct Frecency(u64);
+
+impl From<Frecency> for nvim_router::nvim_rs::Value {
+    fn from(value: Frecency) -> Self {
+        Self::from(value.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Entry {
+    count: u32,
+    last: u64,
+}
+
+impl Entry {
+    fn frecency(self, now: u64) -> Frecency {
+        let age = now.saturating_sub(self.last);
+        let weight = if age < HOUR {
+            8
+        } else if age < DAY {
+            4
+        } else if age < WEEK {
+            2
+        } else {
+            1
+        };
+        Frecency(u64::from(self.count) * weight)
+    }
+}
+
+/// Keeps the `max` most recent entries that are not older than `MAX_AGE`.
+fn prune<T>(entries: &mut HashMap<String, T>, max: usize, last: impl Fn(&T) -> u64, now: u64) {
+    entries.retain(|_, entry| last(entry).saturating_add(MAX_AGE) >= now);
+    if entries.len() <= max {
+        return;
+    }
+
+    let mut lasts = entries.values().map(&last).collect::<Vec<_>>();
+    lasts.sort_unstable_by(|lhs, rhs| rhs.cmp(lhs));
+    let oldest = lasts[max - 1];
+    entries.retain(|_, entry| last(entry) >= oldest);
+
+    // Entries as old as the oldest one kept may exceed the limit.
+    let mut excess = entries.len().saturating_sub(max);
+    entries.retain(|_, entry| {
+        let drop = excess > 0 && last(entry) == oldest;
+        if drop {
+            excess -= 1;
+        }
+        !drop
+    });
+}
+
+fn add_visits(entries: &mut HashMap<String, Entry>, key: &str, visits: Entry) {
+    let entry = entries.entry(key.to_string()).or_default();
+    entry.count = entry.count.saturating_add(visits.count);
+    entry.last = entry.last.max(visits.last);
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Records {
+    /// Visits per file.
+    entries: HashMap<String, Entry>,
+    /// Selections per query and file.
+    selections: HashMap<String, HashMap<String, Entry>>,
+}
+
+impl Records {
+    fn add_selections(&mut self, query: &str, path: &str, selections: Entry) {
+        let entries = self.selections.entry(query.to_string()).or_default();
+        add_visits(entries, path, selections);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.selections.is_empty()
+    }
+
+    fn prune(&mut self, now: u64) {
+        prune(&mut self.entries, MAX_ENTRIES, |entry| entry.last, now);
+        for entries in self.selections.values_mut() {
+            prune(entries, MAX_ENTRIES, |entry| entry.last, now);
+        }
+        self.selections.retain(|_, entries| !entries.is_empty());
+        let last_selected =
+            |entries: &HashMap<String, Entry>| entries.values().map(|entry| entry.last).max();
+        prune(
+            &mut self.selections,
+            MAX_QUERIES,
+            |entries| last_selected(entries).unwrap_or_default(),
+            now,
+        );
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (path, visits) in &other.entries {
+            add_visits(&mut self.entries, path, *visits);
+        }
+        for (query, entries) in &other.selections {
+            for (path, selections) in entries {
+                self.add_selections(query, path, *selections);
+            }
+        }
+    }
+}
+
+/// Records taken out of a `History` to be merged into the store.
+#[derive(Debug, Default)]
+pub(super) struct Pending(Records);
+
+/// How often and how recently each file has been entered, and which files have been selected
+/// for which query.
+#[derive(Debug, Default)]
+pub(super) struct History {
+    records: Records,
+    /// Records not yet merged into the store.
+    pending: Records,
+}
+
+impl History {
+    pub(super) fn visit(&mut self, path: &str, now: u64) {
+        let visit = Entry {
+            count: 1,
+            last: now,
+        };
+        add_visits(&mut self.records.entries, path, visit);
+        add_visits(&mut self.pending.entries, path, visit);
+    }
+
+    pub(super) fn frecency(&self, path: &str, now: u64) -> Frecency {
+        self.records
+            .entries
+            .get(path)
+            .map(|entry| entry.frecency(now))
+            .unwrap_or_default()
+    }
+
+    pub(super) fn select(&mut self, query: &str, path: &str, now: u64) {
+        if query.is_empty() {
+            return;
+        }
+        let selection = Entry {
+            count: 1,
+            last: now,
+        };
+        self.records.add_selections(query, path, selection);
+        self.pending.add_selections(query, path, selection);
+    }
+
+    pub(super) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Takes the records not yet merged into the store, to sync them without the history.
+    pub(super) fn take_pending(&mut self) -> Pending {
+        Pending(std::mem::take(&mut self.pending))
+    }
+
+    /// Replaces the records with those of the store, into which the taken records have been
+    /// merged. The records made since stay pending.
+    pub(super) fn synced(&mut self, store: History) {
+        self.records = store.records;
+        self.records.merge(&self.pending);
+    }
+
+    /// Puts back the taken records after a failed sync, to merge them the next time.
+    pub(super) fn unsynced(&mut self, pending: Pending) {
+        self.pending.merge(&pending.0);
+    }
+
+    /// Keeps a history without a store in memory only: drops the pending records, which would
+    /// never be synced, and forgets the old records as syncing would.
+    pub(super) fn unstored(&mut self, now: u64) {
+        self.pending = Records::default();
+        self.records.prune(now);
+    }
+
+    /// How many times each file has been selected for `query` or for a query it extends.
+    pub(super) fn learned(&self, query: &str) -> Learned<'_> {
+        let mut counts = HashMap::new();
+        if query.is_empty() {
+            return Learned(counts);
+        }
+        for (prefix, entries) in &self.records.selections {
+            if !query.starts_with(prefix.as_str()) {
+                continue;
+            }
+            for (path, entry) in entries {
+                let count: &mut u32 = counts.entry(path.as_str()).or_default();
+                *count = count.saturating_add(entry.count);
+            }
+        }
+        Learned(counts)
+    }
+}
+
+/// The selections learned for a query, see `History::learned`.
+pub(super) struct Learned<'a>(HashMap<&'a str, u32>);
+
+impl Learned<'_> {
+    pub(super) fn get(&self, path: &str) -> u32 {
+        self.0.get(path).copied().unwrap_or_default()
+    }
+}
+
+const HEADER: &str = "buffer-switcher history";
+const VERSION: u32 = 2;
+
+fn parse_entry<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<Entry> {
+    let count = fields.next()?.parse().ok()?;
+    let last = fields.next()?.parse().ok()?;
+    Some(Entry { count, last })
+}
+
+impl History {
+    /// Each line after the header is one of
+    ///
+    /// - `e\t{count}\t{last}\t{path}`: visits of a file, or `{count}\t{last}\t{path}` in v1;
+    /// - `s\t{count}\t{last}\t{query}\t{path}`: selections of a file for a query (since v2).
+    fn parse(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines();
+
+        let version = lines
+            .next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .and_then(|version| version.trim().strip_prefix('v'))
+            .and_then(|version| version.parse::<u32>().ok());
+        match version {
+            Some(1..=VERSION) => {}
+            Some(version) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported history version: {version}"),
+                ));
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "missing history header",
+                ));
+            }
+        }
+
+        let mut records = Records::default();
+        for line in lines {
+            if version == Some(1) {
+                let mut fields = line.splitn(3, '\t');
+                if let Some(entry) = parse_entry(&mut fields)
+                    && let Some(path) = fields.next()
+                {
+                    add_visits(&mut records.entries, path, entry);
+                }
+                continue;
+            }
+
+            let mut fields = line.splitn(5, '\t');
+            match fields.next() {
+                Some("e") => {
+                    let mut fields = line.splitn(4, '\t').skip(1);
+                    if let Some(entry) = parse_entry(&mut fields)
+                        && let Some(path) = fields.next()
+                    {
+                        add_visits(&mut records.entries, path, entry);
+                    }
+                }
+                Some("s") => {
+                    if let Some(entry) = parse_entry(&mut fields)
+                        && let Some(query) = fields.next()
+                        && let Some(path) = fields.next()
+                    {
+                        records.add_selections(query, path, entry);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            records,
+            pending: Records::default(),
+        })
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = format!("{HEADER} v{VERSION}\n");
+        for (path, entry) in &self.records.entries {
+            if path.contains('\n') {
+                continue;
+            }
+            text.push_str(&format!("e\t{}\t{}\t{path}\n", entry.count, entry.last));
+        }
+        for (query, entries) in &self.records.selections {
+            if query.contains(['\t', '\n']) {
+                continue;
+            }
+            for (path, entry) in entries {
+                if path.contains('\n') {
+                    continue;
+                }
+                text.push_str(&format!(
+                    "s\t{}\t{}\t{query}\t{path}\n",
+                    entry.count, entry.last
+                ));
+            }
+        }
+        text
+    }
+}
+
+/// The on-disk copy of a `History`, shared by every Neovim instance on the machine.
+#[derive(Debug, Clone)]
+pub(super) struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    pub(super) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    /// Loads the history. A missing file is an empty history.
+    pub(super) fn load(&self) -> io::Result<History> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => History::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a temporary file and renames it over the store, so that readers never see a
+    /// partially written file.
+    fn write(&self, history: &History) -> io::Result<()> {
+        let tmp = self.sibling(&format!(".{}.tmp", std::process::id()));
+
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(history.serialize().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// Merges the pending records into the store, forgetting the old ones, and returns the
+    /// result, which reflects the visits made by other instances. Fails with `WouldBlock`
+    /// without waiting while another instance is syncing.
+    pub(super) fn sync(&self, pending: &Pending, now: u64) -> io::Result<History> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // The lock lives in a separate file, since the store itself is replaced on every write.
+        let lock = fs::File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.sibling(".lock"))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(io::ErrorKind::WouldBlock.into()),
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+
+        let mut merged = self.load()?;
+        merged.records.merge(&pending.0);
+        merged.records.prune(now);
+        self.write(&merged)?;
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn frecency() {
+        let mut history = History::default();
+        history.visit("/a", 0);
+        history.visit("/a", 0);
+        history.visit("/b", 10 * DAY);
+
+        assert_eq!(history.frecency("/a", 0), Frecency(16));
+        assert_eq!(history.frecency("/a", 2 * DAY), Frecency(4));
+        assert_eq!(history.frecency("/a", 10 * DAY), Frecency(2));
+        assert_eq!(history.frecency("/b", 10 * DAY), Frecency(8));
+        assert_eq!(history.frecency("/c", 10 * DAY), Frecency(0));
+    }
+
+    #[test]
+    fn store_round_trip() {
+        let dir = TempDir::new("store_round_trip");
+        let store = Store::new(dir.join("history"));
+        assert_eq!(store.load().unwrap().records, Records::default());
+
+        let mut history = History::default();
+        history.visit("/a", 1);
+        history.visit("/a", 2);
+        history.visit("/b\tc", 3);
+        history.select("ab", "/a", 4);
+        history.select("a b", "/b\tc", 5);
+        let pending = history.take_pending();
+        history.visit("/d", 6);
+        history.synced(store.sync(&pending, 6).unwrap());
+        assert!(history.has_pending());
+        assert_eq!(history.frecency("/d", 6), Frecency(8));
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.records.entries.len(), 2);
+        assert_eq!(loaded.records.selections, history.records.selections);
+    }
+
+    #[test]
+    fn locked() {
+        let dir = TempDir::new("locked");
+        let store = Store::new(dir.join("history"));
+        let mut history = History::default();
+        history.visit("/a", 1);
+        let pending = history.take_pending();
+        history.synced(store.sync(&pending, 1).unwrap());
+
+        let lock = fs::File::open(store.sibling(".lock")).unwrap();
+        lock.lock().unwrap();
+        history.visit("/b", 2);
+        let pending = history.take_pending();
+        let err = store.sync(&pending, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        history.unsynced(pending);
+
+        lock.unlock().unwrap();
+        let pending = history.take_pending();
+        history.synced(store.sync(&pending, 2).unwrap());
+        assert!(!history.has_pending());
+        assert_eq!(store.load().unwrap().records.entries.len(), 2);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(History::parse("buffer-switcher history v999\n").is_err());
+        assert!(History::parse("1\t2\t/a\n").is_err());
+        assert!(History::parse("buffer-switcher history v1\nbroken\n").is_ok());
+    }
+
+    #[test]
+    fn parse_v1() {
+        let history = History::parse("buffer-switcher history v1\n3\t10\t/a\tb\n").unwrap();
+        assert_eq!(
+            history.records.entries["/a\tb"],
+            Entry { count: 3, last: 10 }
+        );
+    }
+
+    #[test]
+    fn prune() {
+        let mut history = History::default();
+        for i in 0..MAX_ENTRIES as u64 + 10 {
+            history.visit(&format!("/{i}"), MAX_AGE + i);
+        }
+        history.visit("/old", 0);
+        history.select("a", "/old", 0);
+        history.select("b", "/0", MAX_AGE);
+
+        history.records.prune(MAX_AGE + 10);
+        let entries = &history.records.entries;
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert!(!entries.contains_key("/old"));
+        assert!(!entries.contains_key("/9"));
+        assert!(entries.contains_key("/10"));
+        assert_eq!(history.records.selections.keys().collect::<Vec<_>>(), ["b"]);
+    }
+
+    #[test]
+    fn unstored() {
+        let mut history = History::default();
+        history.visit("/old", 0);
+        for i in 0..MAX_ENTRIES as u64 {
+            history.visit(&format!("/{i}"), MAX_AGE + i);
+            history.unstored(MAX_AGE + i);
+        }
+        assert!(!history.has_pending());
+        assert_eq!(history.records.entries.len(), MAX_ENTRIES);
+        assert!(!history.records.entries.contains_key("/old"));
+    }
+
+    #[test]
+    fn learned() {
+        let mut history = History::default();
+        history.select("li", "/lib.rs", 0);
+        history.select("lib", "/lib.rs", 0);
+        history.select("lib", "/src/lib.rs", 0);
+        history.select("", "/main.rs", 0);
+
+        let learned = history.learned("l");
+        assert_eq!(learned.get("/lib.rs"), 0);
+
+        let learned = history.learned("lib.");
+        assert_eq!(learned.get("/lib.rs"), 2);
+        assert_eq!(learned.get("/src/lib.rs"), 1);
+        assert_eq!(learned.get("/main.rs"), 0);
+
+        assert_eq!(history.learned("").get("/main.rs"), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use nvim_router::nvim_rs::Value;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// The device and inode numbers of a file, which are shared by all of its hard links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(metadata: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(_metadata: &fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+/// What a file on this machine is, however it has been opened.
+#[derive(Debug, PartialEq)]
+pub(super) struct Identity {
+    /// The path with every symlink resolved.
+    pub(super) path: String,
+    pub(super) file_id: Option<FileId>,
+}
+
+/// Resolves an absolute path. Fails for a file that does not exist yet.
+pub(super) fn resolve(path: &str) -> Option<Identity> {
+    let canonical = fs::canonicalize(Path::new(path)).ok()?;
+    let file_id = fs::metadata(&canonical)
+        .ok()
+        .and_then(|metadata| FileId::of(&metadata));
+    Some(Identity {
+        path: canonical.into_os_string().into_string().ok()?,
+        file_id,
+    })
+}
+
+/// The identities of the files of the buffers, resolved once per buffer.
+#[derive(Debug, Default)]
+pub(super) struct Cache {
+    /// By buffer id and path.
+    identities: HashMap<(String, String), Identity>,
+    /// The keys looked up since the last `evict`.
+    used: HashSet<(String, String)>,
+}
+
+impl Cache {
+    /// A file that does not exist yet is resolved again the next time.
+    pub(super) fn resolve(&mut self, buf_id: &Value, path: &str) -> Option<&Identity> {
+        let key = (buf_id.to_string(), path.to_string());
+        if !self.identities.contains_key(&key)
+            && let Some(identity) = resolve(path)
+        {
+            self.identities.insert(key.clone(), identity);
+        }
+        self.used.insert(key.clone());
+        self.identities.get(&key)
+    }
+
+    /// Forgets the buffers not looked up since the last call.
+    pub(super) fn evict(&mut self) {
+        let used = std::mem::take(&mut self.used);
+        self.identities.retain(|key, _| used.contains(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn links() {
+        let dir = TempDir::new("links");
+        fs::create_dir_all(dir.join("real")).unwrap();
+        fs::write(dir.join("real/init.lua"), "").unwrap();
+        fs::hard_link(dir.join("real/init.lua"), dir.join("hard.lua")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("config")).unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let real = resolve(&format!("{dir}/real/init.lua")).unwrap();
+        assert_eq!(real.path, format!("{dir}/real/init.lua"));
+
+        #[cfg(unix)]
+        assert_eq!(resolve(&format!("{dir}/config/init.lua")), Some(real));
+
+        let real = resolve(&format!("{dir}/real/init.lua")).unwrap();
+        let hard = resolve(&format!("{dir}/hard.lua")).unwrap();
+        assert_ne!(hard.path, real.path);
+        #[cfg(unix)]
+        assert_eq!(hard.file_id, real.file_id);
+
+        assert_eq!(resolve(&format!("{dir}/missing.lua")), None);
+
+        let mut cache = Cache::default();
+        let id = Value::from(1);
+        let path = format!("{dir}/config/init.lua");
+        let cached = cache
+            .resolve(&id, &path)
+            .map(|identity| identity.path.clone());
+        assert_eq!(cached, Some(format!("{dir}/real/init.lua")));
+        fs::remove_file(format!("{dir}/config")).unwrap();
+        let cached = cache
+            .resolve(&id, &path)
+            .map(|identity| identity.path.clone());
+        assert_eq!(cached, Some(format!("{dir}/real/init.lua")));
+        cache.evict();
+        cache.evict();
+        assert_eq!(cache.resolve(&id, &path), None);
+    }
+}
@@ -1,48 +1,639 @@
 mod buffer_list;
-use buffer_list::{Buffer, BufferId, BufferList};
+use buffer_list::{Buffer, BufferId, BufferList, Focus, TabPreference};
+
+mod closed;
+use closed::{Closed, RecentlyClosed};
+
+mod history;
+use history::{History, Pending, Store};
+
+mod identity;
 
 mod pattern;
-use pattern::{Pattern, Target};
+use pattern::{AmbiWidth, Pattern};
 
 mod rank;
 use rank::{Item as RankingItem, rank};
 
+mod score;
+use score::{BoxedScorer, Context, DefaultScorer, Scorer, Weights};
+
+mod pins;
+use pins::{PinStore, Pins};
+
+mod project;
+use project::Roots;
+
+mod section;
+use section::{Grouping, Section};
+
+mod stale;
+use stale::Stale;
+
+mod uri;
+use uri::Uri;
+
+mod watcher;
+use watcher::Watcher;
+
+#[cfg(test)]
+mod temp_dir;
+
 use nvim_router::NeovimWriter;
 use nvim_router::RpcArgs;
 use nvim_router::nvim_rs::{Neovim, Value};
 
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+/// How long visits are collected before they are written to the history file.
+const SYNC_DELAY: Duration = Duration::from_secs(2);
+/// How many times in a row syncing the history is tried before the failure is reported.
+const MAX_SYNC_FAILURES: u32 = 3;
+
 #[derive(Debug, Default)]
 struct States {
     current_tab: BufferList,
     other_tabs: BufferList,
+    cwd: String,
+    history: History,
+    store: Option<Store>,
+    /// Whether the pending history will be synced with the store shortly.
+    sync_scheduled: bool,
+    /// Failed syncs since the last successful one.
+    sync_failures: u32,
+    weights: Weights,
+    /// The default scorer if not configured.
+    scorer: Option<BoxedScorer>,
+    grouping: Grouping,
+    /// Collapse the copies of a buffer open in several tabs, keeping the preferred one.
+    dedupe: Option<TabPreference>,
+    roots: Roots,
+    identities: identity::Cache,
+    /// The handle of the current tab.
+    tab: Option<i64>,
+    /// The pins of each tab.
+    pins: Pins<i64>,
+    /// The pins saved under the working directory of each tab, restored in the tabs opened there.
+    saved_pins: Pins<String>,
+    /// The working directory each tab had when first seen, which its pins are saved under.
+    pin_dirs: HashMap<i64, String>,
+    pin_store: Option<PinStore>,
+    recently_closed: RecentlyClosed,
+    /// The buffers recently closed in the current tab and not open again.
+    closed_buffers: BufferList,
+    /// The buffers of closed tabs, until they are open in a tab again or unloaded.
+    orphans: Vec<BufferId>,
+    orphaned: BufferList,
+    /// Ranks the buffers of files deleted or changed on disk after the others.
+    demote_stale: bool,
 }
 
 impl States {
-    fn update(&mut self, current_tab: Vec<Value>, other_tabs: Vec<Value>, cwd: &str) {
+    /// `detached` are the buffers loaded but in no tab, in the same form as the others, listed if
+    /// they have been orphaned.
+    fn update(
+        &mut self,
+        current_tab: Vec<Value>,
+        other_tabs: Vec<Value>,
+        detached: Vec<Value>,
+        cwd: &str,
+    ) {
         let home_dir = std::env::home_dir();
         let home_dir = home_dir
             .as_ref()
             .and_then(|path| path.to_str())
             .unwrap_or_default();
 
-        self.current_tab = to_list(current_tab, cwd, home_dir);
-        self.other_tabs = to_list(other_tabs, cwd, home_dir);
+        // A file is checked when its buffer is first listed or loaded again, and is then kept up
+        // to date by the watcher.
+        let checked = self
+            .current_tab
+            .into_iter()
+            .chain(&self.other_tabs)
+            .chain(&self.orphaned)
+            .map(|buf| ((buf.path.clone(), buf.loaded_mtime), buf.stale))
+            .collect::<HashMap<_, _>>();
+
+        self.current_tab = to_list(
+            current_tab,
+            true,
+            cwd,
+            home_dir,
+            &mut self.roots,
+            &mut self.identities,
+        );
+        self.other_tabs = to_list(
+            other_tabs,
+            false,
+            cwd,
+            home_dir,
+            &mut self.roots,
+            &mut self.identities,
+        );
+        if let Some(prefer) = self.dedupe {
+            buffer_list::dedupe(&mut self.current_tab, &mut self.other_tabs, prefer);
+        }
+
+        // Ranked by what Neovim tells of them now, not when their tabs were closed.
+        let orphaned = detached
+            .into_iter()
+            .filter(|buf| buf_item_id(buf).is_some_and(|id| self.orphans.contains(&id)))
+            .collect::<Vec<_>>();
+        self.orphans.retain(|orphan| {
+            orphaned
+                .iter()
+                .any(|buf| buf_item_id(buf).as_ref() == Some(orphan))
+        });
+        self.orphaned = to_list(
+            orphaned,
+            false,
+            cwd,
+            home_dir,
+            &mut self.roots,
+            &mut self.identities,
+        );
+        self.identities.evict();
+
+        for buf in (&mut self.current_tab)
+            .into_iter()
+            .chain(&mut self.other_tabs)
+            .chain(&mut self.orphaned)
+        {
+            buf.stale = match checked.get(&(buf.path.clone(), buf.loaded_mtime)) {
+                Some(stale) => *stale,
+                None => Uri::parse(&buf.path)
+                    .file_path()
+                    .and_then(|path| Stale::check(path, buf.loaded_mtime)),
+            };
+        }
+
+        let closed = self
+            .tab
+            .into_iter()
+            .flat_map(|tab| self.recently_closed.get(tab))
+            .filter(|closed| !self.is_open(&closed.path))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.closed_buffers = closed
+            .into_iter()
+            .map(|closed| closed_to_buffer(closed, cwd, home_dir, &mut self.roots))
+            .collect();
+
+        self.cwd = cwd.to_string();
+    }
+
+    fn load_history(&mut self, path: &str) {
+        let store = Store::new(path);
+        // Keep the file untouched if we cannot understand it.
+        if let Ok(history) = store.load() {
+            self.history = history;
+            self.store = Some(store);
+        }
+    }
+
+    /// Takes what the store has to be synced with, if anything.
+    fn begin_sync(&mut self) -> Option<(Store, Pending)> {
+        self.sync_scheduled = false;
+        let store = self.store.clone()?;
+        self.history
+            .has_pending()
+            .then(|| (store, self.history.take_pending()))
     }
 
-    fn ranking(&self, input: Pattern) -> Value {
-        let current_tab = ranking_to_args(rank(&self.current_tab, input));
-        let other_tabs = ranking_to_args(rank(&self.other_tabs, input));
+    /// Keeps the records pending if the sync has failed, and returns the error to report once
+    /// it has failed too many times in a row.
+    fn end_sync(&mut self, pending: Pending, synced: io::Result<History>) -> Option<String> {
+        match synced {
+            Ok(store) => {
+                self.history.synced(store);
+                self.sync_failures = 0;
+                None
+            }
+            Err(e) => {
+                self.history.unsynced(pending);
+                self.sync_failures += 1;
+                (self.sync_failures == MAX_SYNC_FAILURES)
+                    .then(|| format!("buffer-switcher: cannot save the history: {e}"))
+            }
+        }
+    }
+
+    /// Whether to try syncing again soon after a failure.
+    fn retry_sync(&self) -> bool {
+        !self.sync_scheduled
+            && self.history.has_pending()
+            && (1..MAX_SYNC_FAILURES).contains(&self.sync_failures)
+    }
+
+    fn visit(&mut self, path: &str) {
+        let now = history::now();
+        self.history.visit(&canonical_path(path), now);
+        if self.store.is_none() {
+            self.history.unstored(now);
+        }
+    }
+
+    fn select(&mut self, query: &str, path: &str) {
+        let query = Pattern::from_str(query);
+        let now = history::now();
+        self.history
+            .select(query.as_str(), &canonical_path(path), now);
+        if self.store.is_none() {
+            self.history.unstored(now);
+        }
+    }
+
+    fn load_pins(&mut self, path: &str) {
+        let store = PinStore::new(path);
+        if let Ok(pins) = store.load() {
+            self.saved_pins = pins;
+            self.pin_store = Some(store);
+        }
+    }
+
+    /// Restores the pins saved under `cwd` in a tab seen for the first time.
+    fn enter_tab(&mut self, tab: i64, cwd: &str) {
+        if self.pin_dirs.contains_key(&tab) {
+            return;
+        }
+        let dir = cwd.to_string();
+        self.pins
+            .set_positions(&tab, self.saved_pins.positions(&dir));
+        self.pin_dirs.insert(tab, dir);
+    }
+
+    /// Pins or unpins the file in the tab, and returns whether anything has changed.
+    fn set_pinned(&mut self, tab: i64, path: &str, pinned: bool) -> bool {
+        let path = canonical_path(path);
+        let changed = if pinned {
+            self.pins.pin(&tab, &path)
+        } else {
+            self.pins.unpin(&tab, &path)
+        };
+        if changed && let Some(dir) = self.pin_dirs.get(&tab) {
+            self.saved_pins
+                .set_positions(dir, self.pins.positions(&tab));
+            if let Some(store) = &self.pin_store {
+                let _ = store.save(&self.saved_pins);
+            }
+        }
+        changed
+    }
+
+    fn rename(&mut self, old: &str, new: &str) {
+        // The old name no longer resolves, but the open buffer still has it resolved.
+        let old = self
+            .current_tab
+            .into_iter()
+            .chain(&self.other_tabs)
+            .find(|buf| buf.path == old)
+            .map_or_else(|| canonical_path(old), |buf| buf.canonical_path.clone());
+        let new = canonical_path(new);
+        self.pins.rename(&old, &new);
+        if self.saved_pins.rename(&old, &new)
+            && let Some(store) = &self.pin_store
+        {
+            let _ = store.save(&self.saved_pins);
+        }
+    }
+
+    /// Remembers the buffer as closed in each of `tabs`, and forgets it if it was orphaned.
+    fn remove(&mut self, path: &str, buf_id: &Value, tabs: &[Value]) {
+        let id = BufferId::from_id(buf_id);
+        self.orphans.retain(|orphan| *orphan != id);
+        if path.is_empty() {
+            return;
+        }
+
+        let canonical_path = canonical_path(path);
+        let closed_at = history::now();
+        for tab in tabs.iter().filter_map(Value::as_i64) {
+            self.recently_closed.push(
+                tab,
+                Closed {
+                    path: path.to_string(),
+                    canonical_path: canonical_path.clone(),
+                    buf_id: buf_id.clone(),
+                    closed_at,
+                },
+            );
+        }
+    }
+
+    /// Keeps the buffers still loaded from the closed tab, given as `[buf_id, name]`.
+    fn close_tab(&mut self, tab: i64, buffers: Vec<Value>) {
+        for id in buffers.iter().filter_map(buf_item_id) {
+            if !self.orphans.contains(&id) {
+                self.orphans.push(id);
+            }
+        }
+        // The tab cannot be current again.
+        self.recently_closed.remove_tab(tab);
+        self.pins.remove_tab(&tab);
+        self.pin_dirs.remove(&tab);
+    }
+
+    /// Takes the buffer most recently closed in the tab and not open again.
+    fn reopen_last(&mut self, tab: i64) -> Value {
+        let is_open = |path: &str| {
+            self.current_tab
+                .into_iter()
+                .chain(&self.other_tabs)
+                .any(|buf| buf.path == path)
+        };
+        let Some(closed) = self
+            .recently_closed
+            .take_last(tab, |closed| !is_open(&closed.path))
+        else {
+            return Value::Nil;
+        };
         Value::Map(vec![
-            (Value::from("current_tab"), current_tab),
-            (Value::from("other_tabs"), other_tabs),
+            (Value::from("path"), Value::from(closed.path)),
+            (Value::from("buf_id"), closed.buf_id),
         ])
     }
+
+    /// The directories of the files open or orphaned.
+    fn watched_dirs(&self) -> BTreeSet<PathBuf> {
+        self.current_tab
+            .into_iter()
+            .chain(&self.other_tabs)
+            .chain(&self.orphaned)
+            .filter_map(|buf| Uri::parse(&buf.path).file_path())
+            .filter_map(|path| Path::new(path).parent())
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// Checks again whether the buffers of the file are stale.
+    fn file_changed(&mut self, path: &str) {
+        for buf in (&mut self.current_tab)
+            .into_iter()
+            .chain(&mut self.other_tabs)
+            .chain(&mut self.orphaned)
+            .filter(|buf| buf.path == path)
+        {
+            buf.stale = Stale::check(path, buf.loaded_mtime);
+        }
+    }
+
+    /// Whether a buffer named `path` is open in a tab.
+    fn is_open(&self, path: &str) -> bool {
+        self.current_tab
+            .into_iter()
+            .chain(&self.other_tabs)
+            .any(|buf| buf.path == path)
+    }
+
+    /// The buffer of the file at the canonical `path`.
+    fn find_buffer(&self, path: &str) -> Option<&Buffer> {
+        self.current_tab
+            .into_iter()
+            .chain(&self.other_tabs)
+            .find(|buf| buf.canonical_path == path)
+    }
+
+    /// The file in the numbered slot of the tab, with its buffer id if it is open.
+    fn slot(&self, tab: i64, slot: usize) -> Value {
+        let Some(path) = self.pins.slot(&tab, slot) else {
+            return Value::Nil;
+        };
+        let mut entries = vec![(Value::from("path"), Value::from(path))];
+        if let Some(buf) = self.find_buffer(path) {
+            entries.push((Value::from("buf_id"), Value::from(buf.id.clone())));
+        }
+        Value::Map(entries)
+    }
+
+    fn context(&self) -> Context<'_> {
+        Context {
+            history: &self.history,
+            now: history::now(),
+            weights: &self.weights,
+            demote_stale: self.demote_stale,
+            cwd: &self.cwd,
+            current: self
+                .current_tab
+                .into_iter()
+                .find(|buf| buf.focus == Focus::Current)
+                .map(|buf| &buf.id),
+        }
+    }
+
+    /// The buffers pinned in the current tab, in the order of the pins.
+    fn pinned(&self) -> Vec<&Buffer> {
+        let Some(tab) = self.tab else {
+            return Vec::new();
+        };
+        self.pins
+            .get(&tab)
+            .filter_map(|path| self.find_buffer(path))
+            .collect()
+    }
+
+    fn sections(&self) -> Vec<Section<'_>> {
+        let mut sections = section::group(self.grouping, &self.current_tab, &self.other_tabs);
+
+        let pinned = self.pinned();
+        if !pinned.is_empty() {
+            // Drop the sections only the pinned buffers were in.
+            sections.retain_mut(|section| {
+                let len = section.buffers.len();
+                section
+                    .buffers
+                    .retain(|buf| !pinned.iter().any(|pin| pin.id == buf.id));
+                len == 0 || !section.buffers.is_empty()
+            });
+            sections.insert(0, Section::pinned(pinned));
+        }
+
+        for trailing in [
+            Section::orphaned(&self.orphaned),
+            Section::closed(&self.closed_buffers),
+        ] {
+            if !trailing.buffers.is_empty() {
+                sections.push(trailing);
+            }
+        }
+        sections
+    }
+
+    fn rank_section<'a>(
+        &self,
+        section: &Section<'a>,
+        input: Pattern,
+        ctx: &Context,
+    ) -> Vec<RankingItem<'a>> {
+        let scorer = self
+            .scorer
+            .as_deref()
+            .map_or(&DefaultScorer as &dyn Scorer, |scorer| scorer);
+        let mut items = rank(section.buffers.iter().copied(), input, scorer, ctx)
+            .into_iter()
+            .collect::<Vec<_>>();
+        if section.keep_order {
+            items.sort_by_key(|item| section.buffers.iter().position(|buf| buf.id == item.buf_id));
+        }
+        items
+    }
+
+    fn ranking(&self, input: Pattern, opts: OutputOptions) -> Value {
+        let ctx = self.context();
+
+        let mut sections = self
+            .sections()
+            .into_iter()
+            .map(|section| {
+                let items = self.rank_section(&section, input, &ctx);
+                (section.name, section.title, section.keep_order, items)
+            })
+            .collect::<Vec<_>>();
+
+        if self.grouping == Grouping::Project {
+            let trailing = sections
+                .iter()
+                .position(|(name, ..)| section::TRAILING.contains(&name.as_str()))
+                .unwrap_or(sections.len());
+            let trailing = sections.split_off(trailing);
+
+            let by_recency = input.is_empty();
+            sections.sort_by(|(.., lhs_fixed, lhs), (.., rhs_fixed, rhs)| {
+                // Sections in a fixed order, i.e. the pinned buffers, stay first.
+                rhs_fixed
+                    .cmp(lhs_fixed)
+                    .then_with(|| match (lhs.first(), rhs.first()) {
+                        (Some(lhs), Some(rhs)) => rhs.cmp_rank(lhs, by_recency),
+                        (lhs, rhs) => rhs.is_some().cmp(&lhs.is_some()),
+                    })
+            });
+
+            sections.extend(trailing);
+        }
+
+        let sections = sections
+            .into_iter()
+            .map(|(name, title, _, items)| {
+                Value::Map(vec![
+                    (Value::from("name"), Value::from(name)),
+                    (Value::from("title"), Value::from(title)),
+                    (Value::from("items"), ranking_to_args(items, opts)),
+                ])
+            })
+            .collect();
+        Value::Array(sections)
+    }
+
+    /// Why `buf_id` ranks where it does for `input`.
+    fn explain(&self, input: Pattern, buf_id: &BufferId) -> Value {
+        let by_recency = input.is_empty();
+
+        let ctx = self.context();
+
+        for section in self.sections() {
+            let mut items = self.rank_section(&section, input, &ctx);
+            let Some(pos) = items.iter().position(|item| &item.buf_id == buf_id) else {
+                continue;
+            };
+
+            let outranked_by = items[..pos]
+                .iter()
+                .filter_map(|other| {
+                    let reason = if section.keep_order {
+                        "pin_order"
+                    } else {
+                        other.outranks(&items[pos], by_recency)?
+                    };
+                    Some(Value::Map(vec![
+                        (Value::from("buf_id"), Value::from(other.buf_id.clone())),
+                        (
+                            Value::from("name"),
+                            Value::from(other.content.display_name()),
+                        ),
+                        (Value::from("reason"), Value::from(reason)),
+                    ]))
+                })
+                .collect();
+
+            let item = items.swap_remove(pos);
+            let components = item.components;
+            let candidates = input.candidates(item.content);
+            return Value::Map(vec![
+                (Value::from("buf_id"), Value::from(item.buf_id)),
+                (
+                    Value::from("name"),
+                    Value::from(item.content.display_name()),
+                ),
+                (Value::from("section"), Value::from(section.name)),
+                (
+                    Value::from("in_current_tab"),
+                    Value::from(item.in_current_tab),
+                ),
+                (
+                    Value::from("stale"),
+                    item.stale
+                        .map_or(Value::Nil, |stale| Value::from(stale.name())),
+                ),
+                (Value::from("rank"), Value::from(pos + 1)),
+                (Value::from("bucket"), Value::from(item.bucket.name())),
+                (Value::from("learned"), Value::from(item.learned)),
+                (Value::from("score"), Value::from(item.score)),
+                (
+                    Value::from("components"),
+                    Value::Map(vec![
+                        (Value::from("end_with"), Value::from(components.end_with)),
+                        (Value::from("offset"), Value::from(components.offset)),
+                        (
+                            Value::from("run_length"),
+                            Value::from(components.run_length),
+                        ),
+                        (
+                            Value::from("current_tab"),
+                            Value::from(components.current_tab),
+                        ),
+                        (Value::from("recency"), Value::from(components.recency)),
+                        (Value::from("cwd"), Value::from(components.cwd)),
+                    ]),
+                ),
+                (Value::from("frecency"), Value::from(item.frecency)),
+                (Value::from("last_used"), Value::from(item.last_used)),
+                (
+                    Value::from("candidates"),
+                    Value::Map(vec![
+                        (
+                            Value::from("substring"),
+                            candidates.substring.map(span_to_arg).unwrap_or(Value::Nil),
+                        ),
+                        (
+                            Value::from("fuzzy"),
+                            Value::Array(candidates.fuzzy.into_iter().map(span_to_arg).collect()),
+                        ),
+                    ]),
+                ),
+                (
+                    Value::from("matched"),
+                    Value::Array(item.matched.into_iter().map(span_to_arg).collect()),
+                ),
+                (Value::from("outranked_by"), Value::Array(outranked_by)),
+            ]);
+        }
+
+        Value::Nil
+    }
 }
 
-fn to_list(buffers: Vec<Value>, cwd: &str, home_dir: &str) -> BufferList {
+fn to_list(
+    buffers: Vec<Value>,
+    in_current_tab: bool,
+    cwd: &str,
+    home_dir: &str,
+    roots: &mut Roots,
+    identities: &mut identity::Cache,
+) -> BufferList {
     buffers
         .into_iter()
         .filter_map(move |buf_item| {
@@ -52,24 +643,42 @@ fn to_list(buffers: Vec<Value>, cwd: &str, home_dir: &str) -> BufferList {
                 && let Some(path) = path.as_str()
                 && let Some(metadata) = buf_item.get(2)
             {
-                let path = if let Some(rest) = path.strip_prefix(cwd) {
-                    let mut path = Target::with_capacity(rest.len() + 1);
-                    path.push('.');
-                    path.push_str(rest);
-                    path
-                } else if let Some(rest) = path.strip_prefix(home_dir) {
-                    let mut path = Target::with_capacity(rest.len() + 1);
-                    path.push('~');
-                    path.push_str(rest);
-                    path
+                let info = buf_item.get(3).unwrap_or(&Value::Nil);
+
+                // The same file has the same identity however it has been opened.
+                let identity = Uri::parse(path)
+                    .file_path()
+                    .and_then(|path| identities.resolve(id, path));
+
+                let uri = Uri::parse(path);
+                let file = if path.is_empty() {
+                    let first_line = map_get(info, "first_line").and_then(Value::as_str);
+                    uri::no_name(id, first_line)
                 } else {
-                    Target::from_str(path)
+                    uri.to_target(cwd, home_dir)
                 };
 
                 Some(Buffer {
                     id: BufferId::from_id(id),
-                    file: path,
+                    path: path.to_string(),
+                    canonical_path: identity.map_or(path, |identity| &identity.path).to_string(),
+                    file,
                     metadata: metadata.clone(),
+                    last_used: map_get(info, "lastused")
+                        .and_then(Value::as_u64)
+                        .unwrap_or_default(),
+                    focus: if map_get(info, "current").and_then(Value::as_bool) == Some(true) {
+                        Focus::Current
+                    } else if map_get(info, "alternate").and_then(Value::as_bool) == Some(true) {
+                        Focus::Alternate
+                    } else {
+                        Focus::Other
+                    },
+                    in_current_tab,
+                    project: uri.local_path().and_then(|path| roots.find(path)),
+                    file_id: identity.and_then(|identity| identity.file_id),
+                    loaded_mtime: map_get(info, "mtime").and_then(Value::as_u64),
+                    stale: None,
                 })
             } else {
                 None
@@ -78,7 +687,83 @@ fn to_list(buffers: Vec<Value>, cwd: &str, home_dir: &str) -> BufferList {
         .collect()
 }
 
-fn ranking_to_args<'a>(ranking: impl IntoIterator<Item = RankingItem<'a>>) -> Value {
+fn buf_item_id(buf_item: &Value) -> Option<BufferId> {
+    buf_item
+        .as_array()
+        .and_then(|buf_item| buf_item.first())
+        .map(BufferId::from_id)
+}
+
+/// A closed buffer as listed in the results, not in any tab.
+fn closed_to_buffer(closed: Closed, cwd: &str, home_dir: &str, roots: &mut Roots) -> Buffer {
+    let uri = Uri::parse(&closed.path);
+    Buffer {
+        id: BufferId::from_id(&closed.buf_id),
+        file: uri.to_target(cwd, home_dir),
+        metadata: Value::Array(Vec::new()),
+        last_used: closed.closed_at,
+        focus: Focus::Other,
+        in_current_tab: false,
+        project: uri.local_path().and_then(|path| roots.find(path)),
+        file_id: None,
+        loaded_mtime: None,
+        stale: None,
+        canonical_path: closed.canonical_path,
+        path: closed.path,
+    }
+}
+
+/// Resolves the symlinks in the path of a file, so that history is recorded under one name.
+fn canonical_path(path: &str) -> String {
+    Uri::parse(path)
+        .file_path()
+        .and_then(identity::resolve)
+        .map_or_else(|| path.to_string(), |identity| identity.path)
+}
+
+/// Options of the `rank` request that only affect how the results are reported.
+#[derive(Debug, Default, Clone, Copy)]
+struct OutputOptions {
+    /// Also report char indices and display cells of every highlight range.
+    columns: Option<AmbiWidth>,
+}
+
+impl OutputOptions {
+    fn from_value(opts: &Value) -> Self {
+        let columns = map_get(opts, "columns")
+            .and_then(Value::as_bool)
+            .unwrap_or_default();
+        let ambiwidth = match map_get(opts, "ambiwidth").and_then(Value::as_str) {
+            Some("double") => AmbiWidth::Double,
+            _ => AmbiWidth::Single,
+        };
+
+        Self {
+            columns: columns.then_some(ambiwidth),
+        }
+    }
+}
+
+/// Reads a table argument. Tables, and other values without a dedicated reader such as buffer
+/// ids, are sent wrapped in a one-element list so that they can be read with `next_array`.
+fn next_table(args: &mut RpcArgs) -> Option<Value> {
+    args.next_array()?.into_iter().next()
+}
+
+fn map_get<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    let Value::Map(entries) = map else {
+        return None;
+    };
+    entries
+        .iter()
+        .find(|(k, _)| k.as_str() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn ranking_to_args<'a>(
+    ranking: impl IntoIterator<Item = RankingItem<'a>>,
+    opts: OutputOptions,
+) -> Value {
     let values = ranking
         .into_iter()
         .map(|item| {
@@ -86,26 +771,113 @@ fn ranking_to_args<'a>(ranking: impl IntoIterator<Item = RankingItem<'a>>) -> Va
                 .matched
                 .into_iter()
                 .map(|range| {
-                    Value::Map(vec![
+                    let path_range = item.content.original_range(range.clone());
+                    let basename = item.content.in_basename(&range);
+                    let mut entries = vec![
                         (Value::from("start_idx"), Value::from(range.start)),
                         (Value::from("end_idx"), Value::from(range.end)),
-                    ])
+                        (Value::from("path_start_idx"), Value::from(path_range.start)),
+                        (Value::from("path_end_idx"), Value::from(path_range.end)),
+                        (Value::from("basename"), Value::from(basename)),
+                    ];
+                    if let Some(ambiwidth) = opts.columns {
+                        let columns = item.content.columns(range, ambiwidth);
+                        entries.extend([
+                            (Value::from("start_char"), Value::from(columns.chars.start)),
+                            (Value::from("end_char"), Value::from(columns.chars.end)),
+                            (Value::from("start_col"), Value::from(columns.cells.start)),
+                            (Value::from("end_col"), Value::from(columns.cells.end)),
+                        ]);
+                    }
+                    Value::Map(entries)
                 })
                 .collect();
+            let mut flags = vec![(Value::from("current_tab"), Value::from(item.in_current_tab))];
+            if let Some(stale) = item.stale {
+                flags.push((Value::from("stale"), Value::from(stale.name())));
+            }
             Value::Array(vec![
                 Value::from(item.buf_id),
                 Value::from(item.content.display_name()),
                 item.metadata,
                 Value::Array(matched),
+                Value::from(item.path),
+                Value::Map(vec![
+                    (Value::from("dir"), span_to_arg(item.content.dir_span())),
+                    (
+                        Value::from("basename"),
+                        span_to_arg(item.content.basename_span()),
+                    ),
+                ]),
+                Value::Map(flags),
             ])
         })
         .collect();
     Value::Array(values)
 }
 
+fn span_to_arg(span: std::ops::Range<usize>) -> Value {
+    Value::Map(vec![
+        (Value::from("start_idx"), Value::from(span.start)),
+        (Value::from("end_idx"), Value::from(span.end)),
+    ])
+}
+
+/// Syncs the history with the store, without holding the lock of the states during the I/O.
+/// A failed sync is retried a few times, and then reported once.
+async fn sync_history<W: NeovimWriter>(states: &Mutex<States>, neovim: &Neovim<W>) {
+    loop {
+        let Some((store, pending)) = states.lock().await.begin_sync() else {
+            return;
+        };
+
+        let Ok((pending, synced)) = tokio::task::spawn_blocking(move || {
+            let synced = store.sync(&pending, history::now());
+            (pending, synced)
+        })
+        .await
+        else {
+            return;
+        };
+
+        let (error, retry) = {
+            let mut lock = states.lock().await;
+            let error = lock.end_sync(pending, synced);
+            let retry = lock.retry_sync();
+            lock.sync_scheduled |= retry;
+            (error, retry)
+        };
+        if let Some(error) = error {
+            let _ = neovim.err_writeln(&error).await;
+        }
+        if !retry {
+            return;
+        }
+        tokio::time::sleep(SYNC_DELAY).await;
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct NeovimHandler {
     states: Arc<Mutex<States>>,
+    /// Started on the first update of the buffers.
+    watcher: Arc<Mutex<Option<Watcher>>>,
+}
+
+impl NeovimHandler {
+    /// Syncs the history shortly, together with the visits made until then.
+    fn schedule_sync<W: NeovimWriter>(&self, lock: &mut States, neovim: Neovim<W>) {
+        if lock.sync_scheduled || lock.store.is_none() {
+            return;
+        }
+        lock.sync_scheduled = true;
+
+        let states = self.states.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(SYNC_DELAY).await;
+            sync_history(&states, &neovim).await;
+        });
+    }
 }
 
 impl<W: NeovimWriter> nvim_router::NeovimHandler<W> for NeovimHandler {
@@ -124,16 +896,95 @@ impl<W: NeovimWriter> nvim_router::NeovimHandler<W> for NeovimHandler {
                 return Ok(Value::Nil);
             };
             let input = Pattern::from_str(&input);
+            let opts = next_table(&mut args)
+                .map(|opts| OutputOptions::from_value(&opts))
+                .unwrap_or_default();
 
             let lock = self.states.lock().await;
-            let ret = lock.ranking(input);
+            let ret = lock.ranking(input, opts);
             Ok(ret)
+        } else if name == "explain" {
+            let Some(input) = args.next_string() else {
+                return Ok(Value::Nil);
+            };
+            let input = Pattern::from_str(&input);
+            let Some(buf_id) = next_table(&mut args) else {
+                return Ok(Value::Nil);
+            };
+
+            let lock = self.states.lock().await;
+            let ret = lock.explain(input, &BufferId::from_id(&buf_id));
+            Ok(ret)
+        } else if name == "pin" || name == "unpin" {
+            let Some(path) = args.next_string() else {
+                return Ok(Value::Nil);
+            };
+            let Some(tab) = next_table(&mut args).and_then(|tab| tab.as_i64()) else {
+                return Ok(Value::Nil);
+            };
+            let cwd = args.next_string();
+
+            let mut lock = self.states.lock().await;
+            if let Some(cwd) = cwd {
+                lock.enter_tab(tab, &cwd);
+            }
+            let changed = lock.set_pinned(tab, &path, name == "pin");
+            Ok(Value::from(changed))
+        } else if name == "slot" {
+            let Some(slot) = next_table(&mut args).and_then(|slot| slot.as_u64()) else {
+                return Ok(Value::Nil);
+            };
+            let Some(tab) = next_table(&mut args).and_then(|tab| tab.as_i64()) else {
+                return Ok(Value::Nil);
+            };
+            let cwd = args.next_string();
+
+            let mut lock = self.states.lock().await;
+            if let Some(cwd) = cwd {
+                lock.enter_tab(tab, &cwd);
+            }
+            let ret = lock.slot(tab, slot as usize);
+            Ok(ret)
+        } else if name == "reopen_last" {
+            let Some(tab) = next_table(&mut args).and_then(|tab| tab.as_i64()) else {
+                return Ok(Value::Nil);
+            };
+
+            let mut lock = self.states.lock().await;
+            let ret = lock.reopen_last(tab);
+            Ok(ret)
+        } else if name == "configure" {
+            let Some(config) = next_table(&mut args) else {
+                return Ok(Value::Nil);
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.weights = map_get(&config, "weights")
+                .map(Weights::from_value)
+                .unwrap_or_default();
+            lock.scorer = map_get(&config, "scorer").and_then(score::from_value);
+            lock.grouping = map_get(&config, "group_by")
+                .and_then(Value::as_str)
+                .and_then(Grouping::from_str)
+                .unwrap_or_default();
+            let dedupe = map_get(&config, "dedupe")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            let prefer = map_get(&config, "prefer_tab")
+                .and_then(Value::as_str)
+                .and_then(TabPreference::from_str)
+                .unwrap_or_default();
+            lock.dedupe = dedupe.then_some(prefer);
+            lock.demote_stale = map_get(&config, "demote_stale")
+                .and_then(Value::as_bool)
+                .unwrap_or_default();
+            Ok(Value::Nil)
         } else {
             Ok(Value::Nil)
         }
     }
 
-    async fn handle_notify(&self, name: &str, mut args: RpcArgs, _neovim: Neovim<W>) {
+    async fn handle_notify(&self, name: &str, mut args: RpcArgs, neovim: Neovim<W>) {
         if name == "update_buffers" {
             let Some(current) = args.next_array() else {
                 return;
@@ -145,8 +996,566 @@ impl<W: NeovimWriter> nvim_router::NeovimHandler<W> for NeovimHandler {
                 return;
             };
 
+            let tab = next_table(&mut args).and_then(|tab| tab.as_i64());
+            let detached = args.next_array().unwrap_or_default();
+
+            let dirs = {
+                let mut lock = self.states.lock().await;
+                if let Some(tab) = tab {
+                    lock.enter_tab(tab, &cwd);
+                }
+                lock.tab = tab;
+                lock.update(current, other, detached, &cwd);
+                lock.watched_dirs()
+            };
+
+            let mut watcher = self.watcher.lock().await;
+            watcher::watch(&mut watcher, &self.states, dirs);
+        } else if name == "buffer_entered" {
+            let Some(path) = args.next_string() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.visit(&path);
+            self.schedule_sync(&mut lock, neovim);
+        } else if name == "selected" {
+            let Some(query) = args.next_string() else {
+                return;
+            };
+            let Some(path) = args.next_string() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.select(&query, &path);
+            self.schedule_sync(&mut lock, neovim);
+        } else if name == "load_history" {
+            let Some(path) = args.next_string() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.load_history(&path);
+        } else if name == "buffer_renamed" {
+            let Some(old) = args.next_string() else {
+                return;
+            };
+            let Some(new) = args.next_string() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.rename(&old, &new);
+        } else if name == "buffer_removed" {
+            let Some(path) = args.next_string() else {
+                return;
+            };
+            let Some(buf_id) = next_table(&mut args) else {
+                return;
+            };
+            let Some(tabs) = args.next_array() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.remove(&path, &buf_id, &tabs);
+        } else if name == "tab_closed" {
+            let Some(tab) = next_table(&mut args).and_then(|tab| tab.as_i64()) else {
+                return;
+            };
+            let Some(buffers) = args.next_array() else {
+                return;
+            };
+
+            let mut lock = self.states.lock().await;
+            lock.close_tab(tab, buffers);
+        } else if name == "load_pins" {
+            let Some(path) = args.next_string() else {
+                return;
+            };
+
             let mut lock = self.states.lock().await;
-            lock.update(current, other, &cwd);
+            lock.load_pins(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn sync(states: &mut States) {
+        if let Some((store, pending)) = states.begin_sync() {
+            let synced = store.sync(&pending, history::now());
+            states.end_sync(pending, synced);
         }
     }
+
+    /// A buffer as listed by the plugin, in no tab.
+    fn buf_item(id: i64, name: &str) -> Value {
+        Value::Array(vec![
+            Value::from(id),
+            Value::from(name),
+            Value::Array(Vec::new()),
+        ])
+    }
+
+    /// The names of the sections of a ranking, with the ids of their buffers.
+    fn section_ids(ranking: &Value) -> Vec<(String, Vec<i64>)> {
+        ranking
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|section| {
+                let name = map_get(section, "name").and_then(Value::as_str).unwrap();
+                let ids = map_get(section, "items")
+                    .and_then(Value::as_array)
+                    .unwrap()
+                    .iter()
+                    .map(|item| item[0].as_i64().unwrap())
+                    .collect();
+                (name.to_string(), ids)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shared_history() {
+        let dir = TempDir::new("shared_history");
+        let path = dir.join("history");
+        let path = path.to_str().unwrap();
+
+        let mut first = States::default();
+        let mut second = States::default();
+        first.load_history(path);
+        second.load_history(path);
+
+        let visit = |states: &mut States, path| {
+            states.visit(path);
+            sync(states);
+        };
+        visit(&mut first, "/a");
+        visit(&mut second, "/a");
+        visit(&mut second, "/b");
+        visit(&mut first, "/b");
+
+        let now = history::now();
+        let mut third = States::default();
+        third.load_history(path);
+        for states in [&first, &third] {
+            assert_eq!(
+                states.history.frecency("/a", now),
+                second.history.frecency("/a", now)
+            );
+            assert_eq!(
+                states.history.frecency("/b", now),
+                first.history.frecency("/a", now)
+            );
+        }
+        assert!(second.history.frecency("/a", now) > second.history.frecency("/b", now));
+    }
+
+    #[test]
+    fn open_in_preferred_tab() {
+        let buf = |tab: i64| {
+            Value::Array(vec![
+                Value::from(1),
+                Value::from("/p/a.rs"),
+                Value::Array(vec![Value::from(tab)]),
+            ])
+        };
+        let mut states = States {
+            tab: Some(1),
+            dedupe: Some(TabPreference::Other),
+            ..States::default()
+        };
+        states.update(vec![buf(1)], vec![buf(2)], Vec::new(), "/p");
+
+        // The UI jumps to the first tab of the item, whether or not it is in the current tab.
+        let ranking = states.ranking(Pattern::from_str("a"), OutputOptions::default());
+        let items = ranking
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|section| map_get(section, "items").and_then(Value::as_array).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0][2][0].as_i64(), Some(2));
+        assert_eq!(
+            map_get(&items[0][6], "current_tab").and_then(Value::as_bool),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn explain() {
+        let mut states = States::default();
+        states.update(
+            vec![buf_item(1, "/p/src/lib.rs"), buf_item(2, "/p/lib/mod.rs")],
+            vec![buf_item(3, "/p/lib.rs")],
+            Vec::new(),
+            "/p",
+        );
+
+        let explained = states.explain(
+            Pattern::from_str("lib"),
+            &BufferId::from_id(&Value::from(2)),
+        );
+        assert_eq!(
+            map_get(&explained, "bucket").and_then(Value::as_str),
+            Some("substring")
+        );
+        assert_eq!(map_get(&explained, "rank").and_then(Value::as_u64), Some(2));
+        let outranked_by = map_get(&explained, "outranked_by").and_then(Value::as_array);
+        assert_eq!(
+            outranked_by.map(|items| items
+                .iter()
+                .filter_map(|item| map_get(item, "reason").and_then(Value::as_str))
+                .collect::<Vec<_>>()),
+            Some(vec!["bucket"])
+        );
+
+        let explained = states.explain(
+            Pattern::from_str("lib"),
+            &BufferId::from_id(&Value::from(3)),
+        );
+        assert_eq!(
+            map_get(&explained, "section").and_then(Value::as_str),
+            Some("other_tabs")
+        );
+
+        let explained = states.explain(
+            Pattern::from_str("lib"),
+            &BufferId::from_id(&Value::from(4)),
+        );
+        assert_eq!(explained, Value::Nil);
+    }
+
+    #[test]
+    fn unified() {
+        let mut states = States {
+            grouping: Grouping::All,
+            ..States::default()
+        };
+        states.update(
+            vec![buf_item(1, "/p/lib/mod.rs")],
+            vec![buf_item(2, "/p/src/lib.rs")],
+            Vec::new(),
+            "/p",
+        );
+
+        let ranking = states.ranking(Pattern::from_str("lib"), OutputOptions::default());
+        assert_eq!(section_ids(&ranking), [("all".to_string(), vec![2, 1])]);
+        let items = map_get(&ranking[0], "items")
+            .and_then(Value::as_array)
+            .unwrap();
+        let in_current_tab = items
+            .iter()
+            .map(|item| map_get(&item[6], "current_tab").and_then(Value::as_bool))
+            .collect::<Vec<_>>();
+        assert_eq!(in_current_tab, [Some(false), Some(true)]);
+    }
+
+    #[test]
+    fn group_by_project() {
+        let dir = TempDir::new("group_by_project");
+        std::fs::create_dir_all(dir.join("a/.git")).unwrap();
+        std::fs::create_dir_all(dir.join("b/.git")).unwrap();
+        let buf = |id: i64, name: &str| buf_item(id, &format!("{}/{name}", dir.display()));
+        let mut states = States {
+            grouping: Grouping::Project,
+            ..States::default()
+        };
+        states.update(
+            vec![buf(1, "a/main.rs"), buf(2, "b/main.rs")],
+            vec![buf(3, "b/lib.rs")],
+            Vec::new(),
+            "/",
+        );
+
+        let titles = |input: &str| {
+            let ranking = states.ranking(Pattern::from_str(input), OutputOptions::default());
+            ranking
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|section| {
+                    map_get(section, "title")
+                        .and_then(Value::as_str)
+                        .unwrap()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("lib"), ["b", "a"]);
+        assert_eq!(titles("a/ma"), ["a", "b"]);
+    }
+
+    #[test]
+    fn pinned() {
+        let mut states = States {
+            tab: Some(1),
+            ..States::default()
+        };
+        states.update(
+            vec![
+                buf_item(1, "/p/a.rs"),
+                buf_item(2, "/p/b.rs"),
+                buf_item(3, "/p/c.rs"),
+            ],
+            vec![buf_item(4, "/p/d.rs")],
+            Vec::new(),
+            "/p",
+        );
+        assert!(states.set_pinned(1, "/p/d.rs", true));
+        assert!(states.set_pinned(1, "/p/a.rs", true));
+        assert!(states.set_pinned(2, "/p/b.rs", true));
+
+        let sections = |states: &States, input: &str| {
+            section_ids(&states.ranking(Pattern::from_str(input), OutputOptions::default()))
+        };
+        let expected = [
+            ("pinned".to_string(), vec![4, 1]),
+            ("current_tab".to_string(), vec![2, 3]),
+        ];
+        assert_eq!(sections(&states, ""), expected);
+        assert_eq!(sections(&states, "a.rs"), expected);
+
+        assert!(states.set_pinned(1, "/p/d.rs", false));
+        assert!(!states.set_pinned(1, "/p/d.rs", false));
+        assert_eq!(sections(&states, "")[0], ("pinned".to_string(), vec![1]));
+    }
+
+    #[test]
+    fn slot() {
+        let mut states = States::default();
+        states.update(vec![buf_item(5, "/p/a.rs")], Vec::new(), Vec::new(), "/p");
+        states.set_pinned(1, "/p/b.rs", true);
+        states.set_pinned(1, "/p/a.rs", true);
+
+        assert_eq!(
+            states.slot(1, 1),
+            Value::Map(vec![(Value::from("path"), Value::from("/p/b.rs"))])
+        );
+        assert_eq!(
+            map_get(&states.slot(1, 2), "buf_id").and_then(Value::as_i64),
+            Some(5)
+        );
+        assert_eq!(states.slot(1, 3), Value::Nil);
+
+        states.rename("/p/b.rs", "/p/c.rs");
+        assert_eq!(
+            map_get(&states.slot(1, 1), "path").and_then(Value::as_str),
+            Some("/p/c.rs")
+        );
+    }
+
+    #[test]
+    fn saved_pins() {
+        let mut states = States::default();
+        states.enter_tab(1, "/p");
+        states.set_pinned(1, "/p/a.rs", true);
+        states.enter_tab(2, "/q");
+        states.set_pinned(2, "/q/b.rs", true);
+        states.close_tab(1, Vec::new());
+        assert_eq!(states.slot(1, 1), Value::Nil);
+
+        // Handles are numbered anew, and the same one may be given to a tab elsewhere.
+        states.enter_tab(1, "/q");
+        states.enter_tab(3, "/p");
+        let path = |states: &States, tab: i64| {
+            map_get(&states.slot(tab, 1), "path")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        assert_eq!(path(&states, 1).as_deref(), Some("/q/b.rs"));
+        assert_eq!(path(&states, 3).as_deref(), Some("/p/a.rs"));
+
+        states.set_pinned(1, "/q/b.rs", false);
+        states.enter_tab(4, "/q");
+        assert_eq!(path(&states, 4), None);
+        assert_eq!(path(&states, 2).as_deref(), Some("/q/b.rs"));
+    }
+
+    #[test]
+    fn recently_closed() {
+        let mut states = States {
+            tab: Some(1),
+            ..States::default()
+        };
+        states.remove("/p/a.rs", &Value::from(1), &[Value::from(1)]);
+        states.remove(
+            "/p/b.rs",
+            &Value::from(2),
+            &[Value::from(1), Value::from(2)],
+        );
+        states.remove("/p/c.rs", &Value::from(3), &[Value::from(2)]);
+        states.update(vec![buf_item(4, "/p/b.rs")], Vec::new(), Vec::new(), "/p");
+
+        let ranking = states.ranking(Pattern::from_str(""), OutputOptions::default());
+        assert_eq!(
+            section_ids(&ranking).last(),
+            Some(&(section::CLOSED.to_string(), vec![1]))
+        );
+
+        assert_eq!(
+            map_get(&states.reopen_last(1), "path").and_then(Value::as_str),
+            Some("/p/a.rs")
+        );
+        assert_eq!(states.reopen_last(1), Value::Nil);
+        assert_eq!(
+            map_get(&states.reopen_last(2), "buf_id").and_then(Value::as_i64),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn orphaned() {
+        let mut states = States {
+            tab: Some(1),
+            ..States::default()
+        };
+        let orphan = |id: i64, name: &str| Value::Array(vec![Value::from(id), Value::from(name)]);
+        let detached = |id: i64, name: &str, lastused: u64| {
+            Value::Array(vec![
+                Value::from(id),
+                Value::from(name),
+                Value::Array(Vec::new()),
+                Value::Map(vec![(Value::from("lastused"), Value::from(lastused))]),
+            ])
+        };
+        states.remove("/p/c.rs", &Value::from(3), &[Value::from(2)]);
+        states.close_tab(2, vec![orphan(1, "/p/a.rs"), orphan(2, "/p/b.rs")]);
+        states.close_tab(3, vec![orphan(1, "/p/a.rs")]);
+        states.remove("/p/b.rs", &Value::from(2), &[]);
+        states.update(
+            vec![buf_item(4, "/p/d.rs")],
+            Vec::new(),
+            vec![detached(1, "/p/a.rs", 5), detached(5, "/p/e.rs", 6)],
+            "/p",
+        );
+
+        let names = |states: &States| {
+            let ranking = states.ranking(Pattern::from_str(""), OutputOptions::default());
+            section_ids(&ranking)
+                .into_iter()
+                .filter(|(_, ids)| !ids.is_empty())
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&states), ["current_tab", section::ORPHANED]);
+        let orphaned = states.orphaned.into_iter().collect::<Vec<_>>();
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].last_used, 5);
+        assert_eq!(states.reopen_last(2), Value::Nil);
+
+        states.update(
+            vec![buf_item(4, "/p/d.rs")],
+            vec![buf_item(1, "/p/a.rs")],
+            Vec::new(),
+            "/p",
+        );
+        assert_eq!(names(&states), ["current_tab", "other_tabs"]);
+        assert!(states.orphans.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_name() {
+        let dir = TempDir::new("symlinked_name");
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        std::fs::write(dir.join("real/a.rs"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+        let real = format!("{}/real/a.rs", dir.display());
+        let link = format!("{}/link/a.rs", dir.display());
+
+        let mut states = States {
+            dedupe: Some(TabPreference::Current),
+            ..States::default()
+        };
+        states.update(
+            vec![buf_item(1, &link)],
+            vec![buf_item(2, &real)],
+            Vec::new(),
+            "/",
+        );
+        states.visit(&real);
+
+        let ranking = states.ranking(Pattern::from_str(""), OutputOptions::default());
+        let items = ranking
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|section| map_get(section, "items").and_then(Value::as_array).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0][4].as_str(), Some(link.as_str()));
+
+        let explained = states.explain(Pattern::from_str(""), &BufferId::from_id(&Value::from(1)));
+        assert!(map_get(&explained, "frecency").and_then(Value::as_u64) > Some(0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rename_symlinked() {
+        let dir = TempDir::new("rename_symlinked");
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        std::fs::write(dir.join("real/a.rs"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+        let link = |name: &str| format!("{}/link/{name}", dir.display());
+
+        let mut states = States::default();
+        states.update(
+            vec![buf_item(1, &link("a.rs"))],
+            Vec::new(),
+            Vec::new(),
+            "/",
+        );
+        states.set_pinned(1, &link("a.rs"), true);
+
+        std::fs::rename(dir.join("real/a.rs"), dir.join("real/b.rs")).unwrap();
+        states.rename(&link("a.rs"), &link("b.rs"));
+        assert_eq!(
+            map_get(&states.slot(1, 1), "path").and_then(Value::as_str),
+            Some(format!("{}/real/b.rs", dir.display()).as_str())
+        );
+    }
+
+    #[test]
+    fn stale_checked_once() {
+        let dir = TempDir::new("stale_checked_once");
+        let path = format!("{}/a.rs", dir.display());
+        std::fs::write(&path, "").unwrap();
+
+        let buf = |mtime: u64| {
+            Value::Array(vec![
+                Value::from(1),
+                Value::from(path.as_str()),
+                Value::Array(Vec::new()),
+                Value::Map(vec![(Value::from("mtime"), Value::from(mtime))]),
+            ])
+        };
+        let stale = |states: &States| states.find_buffer(&path).and_then(|buf| buf.stale);
+        let mut states = States::default();
+        states.update(vec![buf(1)], Vec::new(), Vec::new(), "/");
+        assert_eq!(stale(&states), Some(Stale::Changed));
+
+        // Left to the watcher once checked
+        std::fs::remove_file(&path).unwrap();
+        states.update(vec![buf(1)], Vec::new(), Vec::new(), "/");
+        assert_eq!(stale(&states), Some(Stale::Changed));
+        states.file_changed(&path);
+        assert_eq!(stale(&states), Some(Stale::Deleted));
+        states.update(vec![buf(1)], Vec::new(), Vec::new(), "/");
+        assert_eq!(stale(&states), Some(Stale::Deleted));
+
+        // Checked again once loaded again
+        states.update(vec![buf(2)], Vec::new(), Vec::new(), "/");
+        assert_eq!(stale(&states), Some(Stale::Deleted));
+        std::fs::write(&path, "").unwrap();
+        states.update(vec![buf(3)], Vec::new(), Vec::new(), "/");
+        assert_eq!(stale(&states), Some(Stale::Changed));
+    }
 }
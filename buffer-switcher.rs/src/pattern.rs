@@ -31,12 +31,6 @@ mod norm {
             }
         }
 
-        pub(super) fn from_str(s: &str) -> Self {
-            let mut ret = Self::with_capacity(s.len());
-            ret.push_str(s);
-            ret
-        }
-
         pub(super) fn as_str(&self) -> &str {
             &self.inner
         }
@@ -61,6 +55,10 @@ mod norm {
             self.inner.is_empty()
         }
 
+        pub(super) fn as_str(self) -> &'a str {
+            self.inner
+        }
+
         pub(super) fn chars(self) -> Chars<'a> {
             Chars {
                 chars: self.inner.chars(),
@@ -102,32 +100,141 @@ type Range = std::ops::Range<usize>;
 use std::ops::ControlFlow;
 use std::str::CharIndices;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+use unicode_width::UnicodeWidthChar;
+
+/// How East Asian Ambiguous characters are measured, as in Vim's 'ambiwidth'.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum AmbiWidth {
+    #[default]
+    Single,
+    Double,
+}
+
+impl AmbiWidth {
+    fn width(self, c: char) -> usize {
+        let width = match self {
+            Self::Single => c.width(),
+            Self::Double => c.width_cjk(),
+        };
+        width.unwrap_or_default()
+    }
+}
+
+/// A byte range converted into char indices and display cells.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub(super) struct Columns {
+    pub(super) chars: Range,
+    pub(super) cells: Range,
+}
+
+/// A run of the display name starting at `display` that stands for `original` in the
+/// original buffer name. Bytes in a `linear` run correspond one-to-one.
+#[derive(Debug)]
+struct Segment {
+    display: usize,
+    original: Range,
+    linear: bool,
+}
+
+#[derive(Debug)]
 pub(super) struct Target {
     display_name: NormAlloc,
+    origin: Vec<Segment>,
+    original_end: usize,
+    basename: usize,
+}
+
+impl PartialEq for Target {
+    fn eq(&self, other: &Self) -> bool {
+        self.display_name == other.display_name
+    }
+}
+impl Eq for Target {}
+
+impl PartialOrd for Target {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Target {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.display_name.cmp(&other.display_name)
+    }
 }
 
 impl Target {
     pub(super) fn with_capacity(cap: usize) -> Self {
         Self {
             display_name: NormAlloc::with_capacity(cap),
+            origin: Vec::new(),
+            original_end: 0,
+            basename: 0,
         }
     }
 
+    fn push_segment(&mut self, display: usize, original: Range, linear: bool) {
+        if display == self.display_name.len() {
+            return;
+        }
+        if let Some(last) = self.origin.last_mut()
+            && linear
+            && last.linear
+            && last.original.end == original.start
+        {
+            last.original.end = original.end;
+        } else {
+            self.origin.push(Segment {
+                display,
+                original,
+                linear,
+            });
+        }
+    }
+
+    /// Pushes a synthesized character that has no counterpart in the original name.
     pub(super) fn push(&mut self, c: char) {
+        let display = self.display_name.len();
         self.display_name.push(c);
+        let at = self.original_end;
+        self.push_segment(display, at..at, false);
     }
 
+    /// Pushes a synthesized string that has no counterpart in the original name.
     pub(super) fn push_str(&mut self, s: &str) {
+        let display = self.display_name.len();
         self.display_name.push_str(s);
+        let at = self.original_end;
+        self.push_segment(display, at..at, false);
     }
 
-    pub(super) fn from_str(s: &str) -> Self {
-        Self {
-            display_name: NormAlloc::from_str(s),
+    /// Pushes a synthesized string that stands for `original` in the original name.
+    pub(super) fn push_replacement(&mut self, s: &str, original: Range) {
+        let display = self.display_name.len();
+        self.display_name.push_str(s);
+        self.original_end = original.end;
+        self.push_segment(display, original, false);
+    }
+
+    /// Pushes `s` that is found at `offset` in the original name.
+    pub(super) fn push_original(&mut self, s: &str, offset: usize) {
+        for (i, c) in s.char_indices() {
+            let display = self.display_name.len();
+            self.display_name.push(c);
+
+            let original = offset + i..offset + i + c.len_utf8();
+            let linear = self.display_name.len() - display == c.len_utf8();
+            self.original_end = original.end;
+            self.push_segment(display, original, linear);
         }
     }
 
+    #[cfg(test)]
+    pub(super) fn from_str(s: &str) -> Self {
+        let mut ret = Self::with_capacity(s.len());
+        ret.push_original(s, 0);
+        ret
+    }
+
     pub(super) fn display_name(&self) -> &str {
         self.display_name.as_str()
     }
@@ -135,6 +242,73 @@ impl Target {
     pub(super) fn len(&self) -> usize {
         self.display_name.len()
     }
+
+    /// Splits the display name into the directory and the basename at the last `/`. A trailing
+    /// `/` belongs to the basename, so that directories are named by their last component.
+    pub(super) fn split_basename(&mut self) {
+        let name = self.display_name.as_str();
+        let name = name.strip_suffix('/').unwrap_or(name);
+        self.basename = name.rfind('/').map(|i| i + 1).unwrap_or_default();
+    }
+
+    pub(super) fn dir_span(&self) -> Range {
+        0..self.basename
+    }
+
+    pub(super) fn basename_span(&self) -> Range {
+        self.basename..self.len()
+    }
+
+    pub(super) fn in_basename(&self, range: &Range) -> bool {
+        range.start >= self.basename
+    }
+
+    /// Translates a byte range of the display name into char indices and display cells.
+    pub(super) fn columns(&self, range: Range, ambiwidth: AmbiWidth) -> Columns {
+        let mut chars = 0..0;
+        let mut cells = 0..0;
+        for (i, c) in self.display_name.char_indices() {
+            if i >= range.end {
+                break;
+            }
+            if i < range.start {
+                chars.start += 1;
+                cells.start += ambiwidth.width(c);
+            }
+            chars.end += 1;
+            cells.end += ambiwidth.width(c);
+        }
+        Columns { chars, cells }
+    }
+
+    /// Translates a byte range of the display name into a byte range of the original name.
+    pub(super) fn original_range(&self, range: Range) -> Range {
+        let start = {
+            let i = self
+                .origin
+                .partition_point(|seg| seg.display <= range.start);
+            match i.checked_sub(1).map(|i| &self.origin[i]) {
+                Some(seg) if seg.linear => {
+                    let len = seg.original.end - seg.original.start;
+                    seg.original.start + (range.start - seg.display).min(len)
+                }
+                Some(seg) => seg.original.start,
+                None => 0,
+            }
+        };
+        let end = {
+            let i = self.origin.partition_point(|seg| seg.display < range.end);
+            match i.checked_sub(1).map(|i| &self.origin[i]) {
+                Some(seg) if seg.linear => {
+                    let len = seg.original.end - seg.original.start;
+                    seg.original.start + (range.end - seg.display).min(len)
+                }
+                Some(seg) => seg.original.end,
+                None => start,
+            }
+        };
+        start..end.max(start)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -153,6 +327,29 @@ impl<'a> Pattern<'a> {
         self.inner.is_empty()
     }
 
+    pub(super) fn as_str(&self) -> &'a str {
+        self.inner.as_str()
+    }
+
+    /// Whether the pattern is meant to match against the whole path rather than the basename.
+    pub(super) fn has_separator(&self) -> bool {
+        self.inner.chars().any(|c| c == '/')
+    }
+
+    /// Every range the matchers consider for `target`, even the runs of a failed fuzzy match.
+    pub(super) fn candidates(&self, target: &Target) -> Candidates {
+        let substring = SubMatch::from(self, target)
+            .and_then(|mut sub| sub.inner.next())
+            .map(|item| item.range);
+        let fuzzy = self
+            .fuzzy(target)
+            .map(|item| match item {
+                ControlFlow::Continue(item) | ControlFlow::Break(item) => item.range,
+            })
+            .collect();
+        Candidates { substring, fuzzy }
+    }
+
     fn fuzzy<'p, 't>(&'p self, target: &'t Target) -> FuzzyMatch<'p, 't> {
         let mut pat = self.inner.chars();
         let pat_peek = pat.next_back().unwrap_or_default();
@@ -173,6 +370,12 @@ impl<'a> Pattern<'a> {
     }
 }
 
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct Candidates {
+    pub(super) substring: Option<Range>,
+    pub(super) fuzzy: Vec<Range>,
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub(super) struct MatchItem {
     pub(super) range: Range,
@@ -373,10 +576,93 @@ mod tests {
         expect_matches("ΑΒΗ", "Αβη", [(0..("ΑΒΗ".len()), 0)]);
     }
 
+    #[test]
+    fn original_range() {
+        let target = Target::from_str("a\tb\0\u{3}cd\u{3000}e");
+        assert_eq!(target.display_name(), "a bcd e");
+        assert_eq!(target.original_range(0..3), 0..3);
+        assert_eq!(target.original_range(2..4), 2..6);
+        assert_eq!(target.original_range(5..6), 7..10);
+        assert_eq!(target.original_range(5..7), 7..11);
+
+        let mut target = Target::with_capacity(0);
+        target.push_replacement(".", 0..9);
+        target.push_original("/lib.rs", 9);
+        target.push(']');
+        assert_eq!(target.display_name(), "./lib.rs]");
+        assert_eq!(target.original_range(0..1), 0..9);
+        assert_eq!(target.original_range(0..4), 0..12);
+        assert_eq!(target.original_range(2..5), 10..13);
+        assert_eq!(target.original_range(8..9), 16..16);
+    }
+
+    #[test]
+    fn basename() {
+        let expect_spans = |target: &str, dir: Range, basename: Range| {
+            let mut target = Target::from_str(target);
+            target.split_basename();
+            assert_eq!(target.dir_span(), dir);
+            assert_eq!(target.basename_span(), basename);
+        };
+
+        expect_spans("./src/lib.rs", 0..6, 6..12);
+        expect_spans("lib.rs", 0..0, 0..6);
+        expect_spans("[oil] ./src/", 0..8, 8..12);
+        expect_spans("/", 0..0, 0..1);
+    }
+
+    #[test]
+    fn columns() {
+        let expect_columns = |target: &str, range: Range, ambiwidth, chars, cells| {
+            let target = Target::from_str(target);
+            assert_eq!(target.columns(range, ambiwidth), Columns { chars, cells });
+        };
+
+        expect_columns("abcd", 1..3, AmbiWidth::Single, 1..3, 1..3);
+        expect_columns("./日本語.md", 2..8, AmbiWidth::Single, 2..4, 2..6);
+        expect_columns("./日本語.md", 11..14, AmbiWidth::Single, 5..8, 8..11);
+        expect_columns("🦀/lib.rs", 4..8, AmbiWidth::Single, 1..5, 2..6);
+        expect_columns("①/②", 4..7, AmbiWidth::Single, 2..3, 2..3);
+        expect_columns("①/②", 4..7, AmbiWidth::Double, 2..3, 3..5);
+    }
+
     #[test]
     fn pattern_is_empty() {
         assert!(Pattern::from_str("").is_empty());
         assert!(Pattern::from_str("\0\0\0").is_empty());
         assert!(!Pattern::from_str("abc").is_empty());
     }
+
+    #[test]
+    fn pattern_has_separator() {
+        assert!(Pattern::from_str("src/lib").has_separator());
+        assert!(!Pattern::from_str("lib").has_separator());
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn candidates() {
+        let target = Target::from_str("src/lib.rs");
+        assert_eq!(
+            Pattern::from_str("lib").candidates(&target),
+            Candidates {
+                substring: Some(4..7),
+                fuzzy: vec![4..7],
+            }
+        );
+        assert_eq!(
+            Pattern::from_str("lb").candidates(&target),
+            Candidates {
+                substring: None,
+                fuzzy: vec![6..7, 4..5],
+            }
+        );
+        assert_eq!(
+            Pattern::from_str("xb").candidates(&target),
+            Candidates {
+                substring: None,
+                fuzzy: vec![6..7],
+            }
+        );
+    }
 }
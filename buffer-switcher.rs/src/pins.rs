@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The number of slots, which are the first positions of the pins in each tab.
+const SLOTS: usize = 9;
+
+/// Files pinned in each tab, or in each directory as saved. A pin keeps its position until
+/// unpinned, and a new pin takes the first free position.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct Pins<K: Eq + Hash> {
+    tabs: HashMap<K, Vec<Option<String>>>,
+}
+
+impl<K: Eq + Hash + Clone> Pins<K> {
+    /// Returns whether `path` was newly pinned.
+    pub(super) fn pin(&mut self, tab: &K, path: &str) -> bool {
+        let pins = self.tabs.entry(tab.clone()).or_default();
+        if pins.iter().flatten().any(|pin| pin == path) {
+            return false;
+        }
+        match pins.iter_mut().find(|pin| pin.is_none()) {
+            Some(free) => *free = Some(path.to_string()),
+            None => pins.push(Some(path.to_string())),
+        }
+        true
+    }
+
+    /// A position beyond the slots is not trusted, and only puts the pin after the others.
+    fn pin_at(&mut self, tab: &K, position: usize, path: &str) {
+        let pins = self.tabs.entry(tab.clone()).or_default();
+        let position = position.min(pins.len().max(SLOTS));
+        if pins.len() <= position {
+            pins.resize(position + 1, None);
+        }
+        pins[position] = Some(path.to_string());
+    }
+
+    /// Returns whether `path` was pinned.
+    pub(super) fn unpin(&mut self, tab: &K, path: &str) -> bool {
+        let Some(pins) = self.tabs.get_mut(tab) else {
+            return false;
+        };
+        let Some(pin) = pins.iter_mut().find(|pin| pin.as_deref() == Some(path)) else {
+            return false;
+        };
+        *pin = None;
+
+        while pins.last().is_some_and(Option::is_none) {
+            pins.pop();
+        }
+        if pins.is_empty() {
+            self.tabs.remove(tab);
+        }
+        true
+    }
+
+    /// The pins of the tab with their positions.
+    pub(super) fn positions(&self, tab: &K) -> &[Option<String>] {
+        self.tabs.get(tab).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the pins of the tab.
+    pub(super) fn set_positions(&mut self, tab: &K, pins: &[Option<String>]) {
+        if pins.iter().all(Option::is_none) {
+            self.tabs.remove(tab);
+        } else {
+            self.tabs.insert(tab.clone(), pins.to_vec());
+        }
+    }
+
+    pub(super) fn remove_tab(&mut self, tab: &K) {
+        self.tabs.remove(tab);
+    }
+
+    /// Follows a file renamed from `old` to `new` in every tab. Returns whether it was pinned.
+    pub(super) fn rename(&mut self, old: &str, new: &str) -> bool {
+        let mut renamed = false;
+        for pin in self.tabs.values_mut().flatten().flatten() {
+            if pin == old {
+                *pin = new.to_string();
+                renamed = true;
+            }
+        }
+        renamed
+    }
+
+    /// The files pinned in the tab, in the order of their positions.
+    pub(super) fn get(&self, tab: &K) -> impl Iterator<Item = &str> {
+        self.tabs
+            .get(tab)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(String::as_str)
+    }
+
+    /// The file in the numbered slot, from 1 to 9, of the tab.
+    pub(super) fn slot(&self, tab: &K, slot: usize) -> Option<&str> {
+        if !(1..=SLOTS).contains(&slot) {
+            return None;
+        }
+        self.tabs.get(tab)?.get(slot - 1)?.as_deref()
+    }
+}
+
+impl Pins<String> {
+    /// Each line is `{dir}\t{position}\t{path}` with the position counted from 1. Lines of
+    /// older files, which were keyed by tab handles, are skipped.
+    fn parse(text: &str) -> Self {
+        let mut pins = Self::default();
+        for line in text.lines() {
+            let mut fields = line.splitn(3, '\t');
+            if let (Some(dir), Some(position), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+                && Path::new(dir).is_absolute()
+                && let Ok(position) = position.parse::<usize>()
+                && position > 0
+            {
+                pins.pin_at(&dir.to_string(), position - 1, path);
+            }
+        }
+        pins
+    }
+
+    fn serialize(&self) -> String {
+        let mut dirs = self.tabs.iter().collect::<Vec<_>>();
+        dirs.sort_unstable_by_key(|(dir, _)| *dir);
+
+        let mut text = String::new();
+        for (dir, pins) in dirs {
+            if dir.contains(['\t', '\n']) {
+                continue;
+            }
+            for (i, path) in pins.iter().enumerate() {
+                if let Some(path) = path
+                    && !path.contains('\n')
+                {
+                    text.push_str(&format!("{dir}\t{}\t{path}\n", i + 1));
+                }
+            }
+        }
+        text
+    }
+}
+
+/// The on-disk copy of the pins, keyed by the working directories of the tabs since the tab
+/// handles are numbered anew in each session.
+#[derive(Debug)]
+pub(super) struct PinStore {
+    path: PathBuf,
+}
+
+impl PinStore {
+    pub(super) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Loads the pins. A missing file has no pins.
+    pub(super) fn load(&self) -> io::Result<Pins<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(text) => Ok(Pins::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Pins::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(super) fn save(&self, pins: &Pins<String>) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(pins.serialize().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn pinned<K: Eq + Hash + Clone>(pins: &Pins<K>, tab: K) -> Vec<&str> {
+        pins.get(&tab).collect()
+    }
+
+    #[test]
+    fn pin_unpin() {
+        let mut pins = Pins::default();
+        assert!(pins.pin(&1, "/a"));
+        assert!(pins.pin(&1, "/b"));
+        assert!(!pins.pin(&1, "/a"));
+        assert!(pins.pin(&2, "/a"));
+        assert_eq!(pinned(&pins, 1), ["/a", "/b"]);
+
+        assert!(pins.unpin(&1, "/a"));
+        assert!(!pins.unpin(&1, "/a"));
+        assert!(!pins.unpin(&3, "/a"));
+        assert_eq!(pinned(&pins, 1), ["/b"]);
+        assert_eq!(pinned(&pins, 2), ["/a"]);
+        assert!(pinned(&pins, 3).is_empty());
+
+        pins.remove_tab(&2);
+        assert!(pinned(&pins, 2).is_empty());
+    }
+
+    #[test]
+    fn slots() {
+        let mut pins = Pins::default();
+        for path in ["/a", "/b", "/c"] {
+            pins.pin(&1, path);
+        }
+        pins.unpin(&1, "/a");
+        assert_eq!(pins.slot(&1, 1), None);
+        assert_eq!(pins.slot(&1, 2), Some("/b"));
+
+        pins.pin(&1, "/d");
+        assert_eq!(pins.slot(&1, 1), Some("/d"));
+        assert_eq!(pins.slot(&1, 0), None);
+        assert_eq!(pins.slot(&1, 4), None);
+
+        assert!(pins.rename("/b", "/e"));
+        assert!(!pins.rename("/b", "/e"));
+        assert_eq!(pins.slot(&1, 2), Some("/e"));
+        assert_eq!(pinned(&pins, 1), ["/d", "/e", "/c"]);
+
+        pins.unpin(&1, "/c");
+        pins.unpin(&1, "/e");
+        assert_eq!(pins.tabs[&1], [Some("/d".to_string())]);
+    }
+
+    #[test]
+    fn positions() {
+        let mut pins = Pins::default();
+        pins.set_positions(&1, &[None, Some("/b".to_string())]);
+        assert_eq!(pins.slot(&1, 2), Some("/b"));
+        assert_eq!(pins.positions(&1), [None, Some("/b".to_string())]);
+
+        pins.set_positions(&1, &[None]);
+        assert!(pins.positions(&1).is_empty());
+        assert!(pins.tabs.is_empty());
+    }
+
+    #[test]
+    fn store_round_trip() {
+        let dir = TempDir::new("pins");
+        let store = PinStore::new(dir.join("pins"));
+        assert_eq!(store.load().unwrap(), Pins::default());
+
+        let mut pins = Pins::default();
+        let (p, q) = ("/p".to_string(), "/q".to_string());
+        pins.pin(&q, "/b\tc");
+        pins.pin(&p, "/z");
+        pins.pin(&p, "/a");
+        pins.pin(&p, "/y");
+        pins.unpin(&p, "/a");
+        store.save(&pins).unwrap();
+        assert_eq!(store.load().unwrap(), pins);
+    }
+
+    #[test]
+    fn parse_positions() {
+        let pins = Pins::parse("/p\t3\t/c\n/p\t1\t/a\n/q\t1\t/b\tc\n");
+        assert_eq!(pinned(&pins, "/p".to_string()), ["/a", "/c"]);
+        assert_eq!(pins.slot(&"/p".to_string(), 3), Some("/c"));
+        assert_eq!(pins.slot(&"/q".to_string(), 1), Some("/b\tc"));
+
+        let pins = Pins::parse("/p\t99999999999\t/a\n/p\t2\t/b\n/p\t12\t/c\n");
+        assert_eq!(pinned(&pins, "/p".to_string()), ["/b", "/a", "/c"]);
+        assert_eq!(pins.slot(&"/p".to_string(), 2), Some("/b"));
+        assert_eq!(pins.tabs["/p"].len(), SLOTS + 2);
+
+        // Keyed by tab handles
+        let pins = Pins::parse("1\t1\t/a\n1\t/b\n");
+        assert_eq!(pins, Pins::default());
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Entries marking the root of a repository. The whole repository is one project even if it
+/// contains several packages.
+const VCS_MARKERS: &[&str] = &[".git", ".hg", ".jj", ".svn"];
+/// Files marking the root of a project outside repositories.
+const MARKERS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "go.mod",
+    "pyproject.toml",
+    "Makefile",
+];
+
+/// Finds the project roots of files, remembering the result for each directory.
+#[derive(Debug, Default)]
+pub(super) struct Roots {
+    cache: HashMap<String, Option<String>>,
+}
+
+impl Roots {
+    /// The project root of the file at the absolute `path`.
+    pub(super) fn find(&mut self, path: &str) -> Option<String> {
+        let dir = Path::new(path).parent()?.to_str()?;
+        if let Some(root) = self.cache.get(dir) {
+            return root.clone();
+        }
+
+        let root = find_root(Path::new(dir)).and_then(|root| root.to_str().map(str::to_string));
+        self.cache.insert(dir.to_string(), root.clone());
+        root
+    }
+}
+
+fn find_root(dir: &Path) -> Option<&Path> {
+    let has_marker = |dir: &Path, markers: &[&str]| {
+        markers
+            .iter()
+            .any(|marker| dir.join(marker).symlink_metadata().is_ok())
+    };
+
+    dir.ancestors()
+        .find(|dir| has_marker(dir, VCS_MARKERS))
+        .or_else(|| dir.ancestors().find(|dir| has_marker(dir, MARKERS)))
+}
+
+/// The name a project is shown with.
+pub(super) fn title(root: &str) -> &str {
+    let trimmed = root.strip_suffix('/').unwrap_or(root);
+    match trimmed.rsplit_once('/') {
+        Some((_, name)) if !name.is_empty() => name,
+        _ => root,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    use std::fs;
+
+    #[test]
+    fn find() {
+        let dir = TempDir::new("project");
+        fs::create_dir_all(dir.join("repo/.git")).unwrap();
+        fs::create_dir_all(dir.join("repo/crate/src")).unwrap();
+        fs::write(dir.join("repo/crate/Cargo.toml"), "").unwrap();
+        fs::create_dir_all(dir.join("plain/src")).unwrap();
+        fs::write(dir.join("plain/Cargo.toml"), "").unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let mut roots = Roots::default();
+        let repo = format!("{dir}/repo");
+        assert_eq!(
+            roots.find(&format!("{dir}/repo/crate/src/lib.rs")),
+            Some(repo.clone())
+        );
+        assert_eq!(roots.find(&format!("{dir}/repo/README.md")), Some(repo));
+        assert_eq!(
+            roots.find(&format!("{dir}/plain/src/lib.rs")),
+            Some(format!("{dir}/plain"))
+        );
+    }
+
+    #[test]
+    fn project_title() {
+        assert_eq!(title("/home/me/proj"), "proj");
+        assert_eq!(title("/home/me/proj/"), "proj");
+        assert_eq!(title("/"), "/");
+    }
+}
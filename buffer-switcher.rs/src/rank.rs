@@ -1,30 +1,32 @@
-use crate::buffer_list::{Buffer, BufferId, BufferList};
+use crate::buffer_list::{Buffer, BufferId, Focus};
+use crate::history::{Frecency, Learned};
 use crate::pattern::{Pattern, Target};
+use crate::score::{Bucket, Components, Context, Match, Score, Scored, Scorer};
+use crate::stale::Stale;
 
 use nvim_router::nvim_rs::Value;
 
 use std::cmp::Ordering;
 use std::iter::Rev;
-use std::ops::ControlFlow;
-use std::ops::Range;
 
 type VecIntoIter<T> = <Vec<T> as IntoIterator>::IntoIter;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct Score(u16);
-
-impl Score {
-    fn with_penalty(penalty: usize) -> Self {
-        let penalty = penalty.try_into().unwrap_or(u16::MAX);
-        Score(u16::MAX - penalty)
-    }
-}
-
 #[derive(Debug)]
 pub(super) struct Item<'a> {
     pub(super) buf_id: BufferId,
+    pub(super) path: &'a str,
     pub(super) content: &'a Target,
-    score: Score,
+    pub(super) bucket: Bucket,
+    pub(super) components: Components,
+    pub(super) score: Score,
+    pub(super) learned: u32,
+    pub(super) frecency: Frecency,
+    pub(super) focus: Focus,
+    pub(super) last_used: u64,
+    pub(super) in_current_tab: bool,
+    pub(super) stale: Option<Stale>,
+    /// Ranked after the items that are not demoted.
+    pub(super) demoted: bool,
     pub(super) metadata: Value,
     pub(super) matched: Match,
 }
@@ -44,8 +46,10 @@ impl PartialOrd for Item<'_> {
 
 impl Ord for Item<'_> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.score
-            .cmp(&other.score)
+        self.learned
+            .cmp(&other.learned)
+            .then_with(|| self.score.cmp(&other.score))
+            .then_with(|| self.frecency.cmp(&other.frecency))
             .then_with(|| other.content.len().cmp(&self.content.len()))
             .then_with(|| other.content.cmp(self.content))
             .then_with(|| self.buf_id.cmp(&other.buf_id))
@@ -53,78 +57,137 @@ impl Ord for Item<'_> {
 }
 
 impl<'a> Item<'a> {
-    fn from(buf: &'a Buffer, score: Score, matched: Match) -> Self {
-        Self {
-            buf_id: buf.id.clone(),
-            content: &buf.file,
-            score,
-            metadata: buf.metadata.clone(),
-            matched,
-        }
+    /// The criteria of the ranking in order of precedence, each with how `self` compares to
+    /// `other`.
+    fn criteria(
+        &self,
+        other: &Self,
+        by_recency: bool,
+    ) -> impl Iterator<Item = (&'static str, Ordering)> {
+        let demoted = ("stale", other.demoted.cmp(&self.demoted));
+        let recency = [
+            ("focus", self.focus.cmp(&other.focus)),
+            ("last_used", self.last_used.cmp(&other.last_used)),
+        ];
+        let bucket = if self.learned > 0 {
+            Ordering::Equal
+        } else {
+            self.bucket.cmp(&other.bucket)
+        };
+        let relevance = [
+            ("learned", (self.learned > 0).cmp(&(other.learned > 0))),
+            ("bucket", bucket),
+            ("learned", self.learned.cmp(&other.learned)),
+            ("score", self.score.cmp(&other.score)),
+            ("frecency", self.frecency.cmp(&other.frecency)),
+            ("length", other.content.len().cmp(&self.content.len())),
+            ("name", other.content.cmp(self.content)),
+            ("buf_id", self.buf_id.cmp(&other.buf_id)),
+        ];
+
+        let recency_len = if by_recency { recency.len() } else { 0 };
+        std::iter::once(demoted)
+            .chain(recency.into_iter().take(recency_len))
+            .chain(relevance)
     }
-}
-
-#[derive(Debug)]
-pub(super) enum Match {
-    Sub(Range<usize>),
-    Fuzzy(Vec<Range<usize>>),
-    None,
-}
 
-impl IntoIterator for Match {
-    type Item = Range<usize>;
-    type IntoIter = MatchIntoIter;
-
-    fn into_iter(self) -> Self::IntoIter {
-        use std::iter::{empty, once};
-        match self {
-            Self::Sub(v) => MatchIntoIter::Sub(once(v)),
-            Self::Fuzzy(v) => MatchIntoIter::Fuzzy(v.into_iter()),
-            Self::None => MatchIntoIter::None(empty()),
-        }
+    /// The first criterion by which `self` ranks above `other`, if it does.
+    pub(super) fn outranks(&self, other: &Self, by_recency: bool) -> Option<&'static str> {
+        self.criteria(other, by_recency)
+            .find(|(_, ord)| ord.is_ne())
+            .and_then(|(reason, ord)| ord.is_gt().then_some(reason))
     }
-}
 
-pub(super) enum MatchIntoIter {
-    Sub(std::iter::Once<Range<usize>>),
-    Fuzzy(VecIntoIter<Range<usize>>),
-    None(std::iter::Empty<Range<usize>>),
-}
+    /// Compares items of different rankings as if they were ranked together.
+    pub(super) fn cmp_rank(&self, other: &Self, by_recency: bool) -> Ordering {
+        self.criteria(other, by_recency)
+            .map(|(_, ord)| ord)
+            .find(|ord| ord.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
 
-impl Iterator for MatchIntoIter {
-    type Item = Range<usize>;
+    /// Orders the demoted items below the others.
+    fn cmp_demoted(&self, other: &Self) -> Ordering {
+        other.demoted.cmp(&self.demoted)
+    }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = match self {
-            Self::Sub(it) => it.len(),
-            Self::Fuzzy(it) => it.len(),
-            Self::None(it) => it.len(),
-        };
-        (len, Some(len))
+    /// Orders by how recently the buffers have been used, with the alternate buffer as the most
+    /// recent one and the current buffer as the least recent one.
+    fn cmp_recency(&self, other: &Self) -> Ordering {
+        self.focus
+            .cmp(&other.focus)
+            .then_with(|| self.last_used.cmp(&other.last_used))
+            .then_with(|| self.cmp(other))
     }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self {
-            Self::Sub(it) => it.next(),
-            Self::Fuzzy(it) => it.next(),
-            Self::None(it) => it.next(),
+    fn from(buf: &'a Buffer, scored: Scored, learned: &Learned, ctx: &Context) -> Self {
+        let Scored {
+            bucket,
+            components,
+            matched,
+        } = scored;
+        let learned = match matched {
+            Match::None => 0,
+            _ => learned.get(&buf.canonical_path),
+        };
+        Self {
+            buf_id: buf.id.clone(),
+            path: &buf.path,
+            content: &buf.file,
+            bucket,
+            components,
+            score: components.sum(),
+            learned,
+            frecency: ctx.history.frecency(&buf.canonical_path, ctx.now),
+            focus: buf.focus,
+            last_used: buf.last_used,
+            in_current_tab: buf.in_current_tab,
+            stale: buf.stale,
+            demoted: ctx.demote_stale && buf.stale.is_some(),
+            metadata: buf.metadata.clone(),
+            matched,
         }
     }
 }
 
 #[derive(Debug, Default)]
 pub(super) struct RankedItems<'a> {
-    end_with: Vec<Item<'a>>,
+    /// Matched items that have been selected for the same or a shorter query before.
+    learned: Vec<Item<'a>>,
+    basename: Vec<Item<'a>>,
+    basename_fuzzy: Vec<Item<'a>>,
     substring: Vec<Item<'a>>,
     fuzzy: Vec<Item<'a>>,
+    /// Matched items of stale files, in any bucket.
+    demoted: Vec<Item<'a>>,
+    /// The demoted ones last.
     nonmatch: Vec<Item<'a>>,
 }
 
-impl RankedItems<'_> {
+impl<'a> RankedItems<'a> {
+    fn push(&mut self, item: Item<'a>) {
+        let items = match item.bucket {
+            Bucket::Nonmatch => &mut self.nonmatch,
+            _ if item.demoted => &mut self.demoted,
+            _ if item.learned > 0 => &mut self.learned,
+            Bucket::Basename => &mut self.basename,
+            Bucket::BasenameFuzzy => &mut self.basename_fuzzy,
+            Bucket::Substring => &mut self.substring,
+            Bucket::Fuzzy => &mut self.fuzzy,
+        };
+        items.push(item);
+    }
+
     fn sort(&mut self) {
-        self.end_with.sort_unstable();
+        self.learned.sort_unstable();
+        self.basename.sort_unstable();
+        self.basename_fuzzy.sort_unstable();
         self.substring.sort_unstable();
         self.fuzzy.sort_unstable();
+        self.demoted
+            .sort_unstable_by(|lhs, rhs| lhs.cmp_rank(rhs, false));
+        self.nonmatch
+            .sort_unstable_by(|lhs, rhs| lhs.cmp_demoted(rhs).then_with(|| lhs.cmp(rhs)));
     }
 }
 
@@ -134,32 +197,49 @@ impl<'a> IntoIterator for RankedItems<'a> {
 
     fn into_iter(self) -> Self::IntoIter {
         RankingIntoIter {
-            end_with: self.end_with.into_iter().rev(),
+            learned: self.learned.into_iter().rev(),
+            basename: self.basename.into_iter().rev(),
+            basename_fuzzy: self.basename_fuzzy.into_iter().rev(),
             substring: self.substring.into_iter().rev(),
             fuzzy: self.fuzzy.into_iter().rev(),
-            nonmatch: self.nonmatch.into_iter(),
+            demoted: self.demoted.into_iter().rev(),
+            nonmatch: self.nonmatch.into_iter().rev(),
         }
     }
 }
 
 pub(super) struct RankingIntoIter<'a> {
-    end_with: Rev<VecIntoIter<Item<'a>>>,
+    learned: Rev<VecIntoIter<Item<'a>>>,
+    basename: Rev<VecIntoIter<Item<'a>>>,
+    basename_fuzzy: Rev<VecIntoIter<Item<'a>>>,
     substring: Rev<VecIntoIter<Item<'a>>>,
     fuzzy: Rev<VecIntoIter<Item<'a>>>,
-    nonmatch: VecIntoIter<Item<'a>>,
+    demoted: Rev<VecIntoIter<Item<'a>>>,
+    nonmatch: Rev<VecIntoIter<Item<'a>>>,
 }
 
 impl<'a> Iterator for RankingIntoIter<'a> {
     type Item = Item<'a>;
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let len =
-            self.end_with.len() + self.substring.len() + self.fuzzy.len() + self.nonmatch.len();
+        let len = self.learned.len()
+            + self.basename.len()
+            + self.basename_fuzzy.len()
+            + self.substring.len()
+            + self.fuzzy.len()
+            + self.demoted.len()
+            + self.nonmatch.len();
         (len, Some(len))
     }
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(item) = self.end_with.next() {
+        if let Some(item) = self.learned.next() {
+            return Some(item);
+        }
+        if let Some(item) = self.basename.next() {
+            return Some(item);
+        }
+        if let Some(item) = self.basename_fuzzy.next() {
             return Some(item);
         }
         if let Some(item) = self.substring.next() {
@@ -168,77 +248,276 @@ impl<'a> Iterator for RankingIntoIter<'a> {
         if let Some(item) = self.fuzzy.next() {
             return Some(item);
         }
+        if let Some(item) = self.demoted.next() {
+            return Some(item);
+        }
         self.nonmatch.next()
     }
 }
 
-pub(super) fn rank<'a>(buffers: &'a BufferList, input: Pattern) -> RankedItems<'a> {
+pub(super) fn rank<'a>(
+    buffers: impl IntoIterator<Item = &'a Buffer> + Clone,
+    input: Pattern,
+    scorer: &dyn Scorer,
+    ctx: &Context,
+) -> RankedItems<'a> {
+    let learned = ctx.history.learned(input.as_str());
+    let mut ranking = RankedItems::default();
+
     if input.is_empty() {
-        let mut ranking = RankedItems {
-            nonmatch: buffers
-                .into_iter()
-                .map(|target| Item::from(target, Score(0), Match::None))
-                .collect(),
-            ..Default::default()
-        };
+        for target in buffers {
+            ranking.push(Item::from(target, Scored::nonmatch(), &learned, ctx));
+        }
 
-        ranking.sort();
+        ranking
+            .nonmatch
+            .sort_unstable_by(|lhs, rhs| lhs.cmp_demoted(rhs).then_with(|| lhs.cmp_recency(rhs)));
         return ranking;
     }
 
-    let mut ranking = RankedItems::default();
+    let mut last_used = buffers
+        .clone()
+        .into_iter()
+        .map(|buf| buf.last_used)
+        .collect::<Vec<_>>();
+    last_used.sort_unstable();
+
+    for target in buffers {
+        let mut scored = scorer.score(input, target, ctx);
 
-    't: for target in buffers {
-        let mut tester = input.test(&target.file);
-
-        match tester.next() {
-            Some(ControlFlow::Break(item)) => {
-                if item.roffset == 0 {
-                    ranking
-                        .end_with
-                        .push(Item::from(target, Score(0), Match::Sub(item.range)));
-                } else {
-                    ranking.substring.push(Item::from(
-                        target,
-                        Score::with_penalty(item.roffset),
-                        Match::Sub(item.range),
-                    ));
-                }
-            }
-            Some(ControlFlow::Continue(item)) => {
-                let mut matched = vec![item.range];
-
-                for item in tester {
-                    match item {
-                        ControlFlow::Break(item) => {
-                            let penalty = item.roffset + (item.range.end - item.range.start);
-                            let score = Score::with_penalty(penalty);
-
-                            matched.push(item.range);
-
-                            ranking
-                                .fuzzy
-                                .push(Item::from(target, score, Match::Fuzzy(matched)));
-                            continue 't;
-                        }
-                        ControlFlow::Continue(item) => {
-                            matched.push(item.range);
-                        }
-                    }
-                }
-                ranking
-                    .nonmatch
-                    .push(Item::from(target, Score(0), Match::None));
-            }
-            None => {
-                ranking
-                    .nonmatch
-                    .push(Item::from(target, Score(0), Match::None));
-            }
+        // The number of buffers used less recently than this one.
+        let older = last_used.partition_point(|&t| t < target.last_used) as i64;
+        if target.in_current_tab {
+            scored.components.current_tab = ctx.weights.current_tab;
         }
+        scored.components.recency = ctx.weights.recency.saturating_mul(older);
+
+        ranking.push(Item::from(target, scored, &learned, ctx));
     }
 
     ranking.sort();
 
     ranking
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer_list::BufferList;
+    use crate::history::History;
+    use crate::score::{DefaultScorer, Weights};
+
+    fn context<'a>(history: &'a History, weights: &'a Weights) -> Context<'a> {
+        Context {
+            history,
+            now: 0,
+            weights,
+            demote_stale: false,
+            cwd: "",
+            current: None,
+        }
+    }
+
+    fn expect_ranking(files: &[&str], pat: &str, expected: &[&str]) {
+        expect_ranking_with(files, pat, &History::default(), expected);
+    }
+
+    fn expect_ranking_with(files: &[&str], pat: &str, history: &History, expected: &[&str]) {
+        let buffers = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| Buffer::for_test(i as i64, file))
+            .collect::<BufferList>();
+
+        let ranking = rank(
+            &buffers,
+            Pattern::from_str(pat),
+            &DefaultScorer,
+            &context(history, &Weights::default()),
+        )
+        .into_iter()
+        .map(|item| item.path)
+        .collect::<Vec<_>>();
+        assert_eq!(ranking, expected);
+    }
+
+    #[test]
+    fn prefer_basename() {
+        expect_ranking(
+            &["lib/foo/bar.rs", "src/lib.rs"],
+            "lib",
+            &["src/lib.rs", "lib/foo/bar.rs"],
+        );
+        expect_ranking(
+            &["lb/foo/bar.rs", "src/lib.rs"],
+            "lb",
+            &["src/lib.rs", "lb/foo/bar.rs"],
+        );
+        expect_ranking(&["a/lib.rs", "lib/a.rs"], "lib/", &["lib/a.rs", "a/lib.rs"]);
+    }
+
+    #[test]
+    fn frecency() {
+        let mut history = History::default();
+        history.visit("b/lib.rs", 0);
+        history.visit("b/lib.rs", 0);
+        history.visit("c/lib.rs", 0);
+
+        let files = ["a/lib.rs", "b/lib.rs", "c/lib.rs"];
+        expect_ranking_with(&files, "", &history, &["b/lib.rs", "c/lib.rs", "a/lib.rs"]);
+        expect_ranking_with(
+            &files,
+            "lib",
+            &history,
+            &["b/lib.rs", "c/lib.rs", "a/lib.rs"],
+        );
+    }
+
+    #[test]
+    fn learned() {
+        let mut history = History::default();
+        history.select("li", "a/lib.rs", 0);
+
+        let files = ["a/lib.rs", "b/lib", "c/main.rs"];
+        expect_ranking_with(&files, "lib", &history, &["a/lib.rs", "b/lib", "c/main.rs"]);
+        expect_ranking_with(&files, "l", &history, &["b/lib", "a/lib.rs", "c/main.rs"]);
+        expect_ranking_with(&files, "lx", &history, &["b/lib", "a/lib.rs", "c/main.rs"]);
+    }
+
+    #[test]
+    fn alternate_first() {
+        let mut history = History::default();
+        history.visit("a", 0);
+
+        let buffers = [
+            ("a", 30, Focus::Other),
+            ("b", 20, Focus::Alternate),
+            ("c", 40, Focus::Current),
+            ("d", 10, Focus::Other),
+            ("e", 50, Focus::Other),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (file, last_used, focus))| Buffer {
+            last_used,
+            focus,
+            ..Buffer::for_test(i as i64, file)
+        })
+        .collect::<BufferList>();
+
+        let ranking = rank(
+            &buffers,
+            Pattern::from_str(""),
+            &DefaultScorer,
+            &context(&history, &Weights::default()),
+        )
+        .into_iter()
+        .map(|item| item.path)
+        .collect::<Vec<_>>();
+        assert_eq!(ranking, ["b", "e", "a", "d", "c"]);
+    }
+
+    #[test]
+    fn end_with_first() {
+        expect_ranking(
+            &["src/main.rs", "main/rs.toml"],
+            "rs",
+            &["src/main.rs", "main/rs.toml"],
+        );
+    }
+
+    #[test]
+    fn weights() {
+        let buffers = [("src/main.rs", 20), ("main/rs.toml", 10)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (file, last_used))| Buffer {
+                last_used,
+                ..Buffer::for_test(i as i64, file)
+            })
+            .collect::<BufferList>();
+        let ranking = |weights: &Weights| {
+            rank(
+                &buffers,
+                Pattern::from_str("rs"),
+                &DefaultScorer,
+                &context(&History::default(), weights),
+            )
+            .into_iter()
+            .map(|item| item.path)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ranking(&Weights::default()),
+            ["src/main.rs", "main/rs.toml"]
+        );
+        assert_eq!(
+            ranking(&Weights {
+                end_with: -100,
+                ..Weights::default()
+            }),
+            ["main/rs.toml", "src/main.rs"]
+        );
+        assert_eq!(
+            ranking(&Weights {
+                offset: 0,
+                end_with: 0,
+                recency: -1,
+                ..Weights::default()
+            }),
+            ["main/rs.toml", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn demote_stale() {
+        let buffers = [
+            ("src/lib.rs", 30, Some(Stale::Deleted)),
+            ("src/main.rs", 20, None),
+            ("lib/mod.rs", 10, Some(Stale::Changed)),
+            ("README.md", 0, None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (file, last_used, stale))| Buffer {
+            last_used,
+            stale,
+            ..Buffer::for_test(i as i64, file)
+        })
+        .collect::<BufferList>();
+        let history = History::default();
+        let weights = Weights::default();
+        let ranking = |input: &str, demote_stale: bool| {
+            rank(
+                &buffers,
+                Pattern::from_str(input),
+                &DefaultScorer,
+                &Context {
+                    demote_stale,
+                    ..context(&history, &weights)
+                },
+            )
+            .into_iter()
+            .map(|item| item.path)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ranking("", true),
+            ["src/main.rs", "README.md", "src/lib.rs", "lib/mod.rs"]
+        );
+        assert_eq!(
+            ranking("lib", false),
+            ["src/lib.rs", "lib/mod.rs", "README.md", "src/main.rs"]
+        );
+        assert_eq!(
+            ranking("lib", true),
+            ["src/lib.rs", "lib/mod.rs", "README.md", "src/main.rs"]
+        );
+        assert_eq!(
+            ranking("rs", true),
+            ["src/main.rs", "lib/mod.rs", "src/lib.rs", "README.md"]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use crate::buffer_list::{Buffer, BufferId};
+use crate::history::History;
+use crate::pattern::Pattern;
+use crate::uri::Uri;
+
+use nvim_router::nvim_rs::Value;
+
+use std::fmt::Debug;
+use std::ops::ControlFlow;
+use std::ops::Range;
+
+type VecIntoIter<T> = <Vec<T> as IntoIterator>::IntoIter;
+
+/// Higher is better. Only comparable within the same bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct Score(i64);
+
+impl From<Score> for Value {
+    fn from(value: Score) -> Self {
+        Value::from(value.0)
+    }
+}
+
+impl Score {
+    pub(super) fn add(self, bonus: i64) -> Self {
+        Score(self.0.saturating_add(bonus))
+    }
+}
+
+/// The terms a score is the sum of, as reported by `explain`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Components {
+    pub(super) end_with: i64,
+    pub(super) offset: i64,
+    pub(super) run_length: i64,
+    pub(super) current_tab: i64,
+    pub(super) recency: i64,
+    pub(super) cwd: i64,
+}
+
+impl Components {
+    pub(super) fn sum(&self) -> Score {
+        Score::default()
+            .add(self.end_with)
+            .add(self.offset)
+            .add(self.run_length)
+            .add(self.current_tab)
+            .add(self.recency)
+            .add(self.cwd)
+    }
+}
+
+/// The kind of a match. A better bucket always ranks higher regardless of the score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Bucket {
+    Nonmatch,
+    Fuzzy,
+    Substring,
+    BasenameFuzzy,
+    Basename,
+}
+
+impl Bucket {
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Nonmatch => "nonmatch",
+            Self::Fuzzy => "fuzzy",
+            Self::Substring => "substring",
+            Self::BasenameFuzzy => "basename_fuzzy",
+            Self::Basename => "basename",
+        }
+    }
+}
+
+/// The constants the scores are made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Weights {
+    /// Bonus for a substring match that ends at the end of the name.
+    pub(super) end_with: i64,
+    /// Penalty per byte between the end of a match and the end of the name.
+    pub(super) offset: i64,
+    /// Penalty per byte of the last run of a fuzzy match.
+    pub(super) run_length: i64,
+    /// Bonus for a buffer in the current tab.
+    pub(super) current_tab: i64,
+    /// Bonus per buffer that has been used less recently.
+    pub(super) recency: i64,
+    /// Bonus for a file under the working directory, given by the `cwd` scorer.
+    pub(super) cwd: i64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            end_with: 100,
+            offset: 1,
+            run_length: 1,
+            current_tab: 10,
+            recency: 0,
+            cwd: 10,
+        }
+    }
+}
+
+impl Weights {
+    /// Overrides the defaults with the integers given in `map`.
+    pub(super) fn from_value(map: &Value) -> Self {
+        let get = |key: &str, default: i64| {
+            crate::map_get(map, key)
+                .and_then(Value::as_i64)
+                .unwrap_or(default)
+        };
+        let default = Self::default();
+
+        Self {
+            end_with: get("end_with", default.end_with),
+            offset: get("offset", default.offset),
+            run_length: get("run_length", default.run_length),
+            current_tab: get("current_tab", default.current_tab),
+            recency: get("recency", default.recency),
+            cwd: get("cwd", default.cwd),
+        }
+    }
+
+    /// The (negative) component for `len` bytes penalized by `weight` each.
+    fn penalty(weight: i64, len: usize) -> i64 {
+        weight
+            .saturating_mul(len.try_into().unwrap_or(i64::MAX))
+            .saturating_neg()
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum Match {
+    Sub(Range<usize>),
+    Fuzzy(Vec<Range<usize>>),
+    None,
+}
+
+impl IntoIterator for Match {
+    type Item = Range<usize>;
+    type IntoIter = MatchIntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        use std::iter::{empty, once};
+        match self {
+            Self::Sub(v) => MatchIntoIter::Sub(once(v)),
+            Self::Fuzzy(v) => MatchIntoIter::Fuzzy(v.into_iter()),
+            Self::None => MatchIntoIter::None(empty()),
+        }
+    }
+}
+
+pub(super) enum MatchIntoIter {
+    Sub(std::iter::Once<Range<usize>>),
+    Fuzzy(VecIntoIter<Range<usize>>),
+    None(std::iter::Empty<Range<usize>>),
+}
+
+impl Iterator for MatchIntoIter {
+    type Item = Range<usize>;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = match self {
+            Self::Sub(it) => it.len(),
+            Self::Fuzzy(it) => it.len(),
+            Self::None(it) => it.len(),
+        };
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Sub(it) => it.next(),
+            Self::Fuzzy(it) => it.next(),
+            Self::None(it) => it.next(),
+        }
+    }
+}
+
+/// What a scorer knows besides the pattern and the buffer.
+pub(super) struct Context<'a> {
+    pub(super) history: &'a History,
+    pub(super) now: u64,
+    pub(super) weights: &'a Weights,
+    /// Ranks the buffers of stale files after the others.
+    pub(super) demote_stale: bool,
+    /// The working directory.
+    pub(super) cwd: &'a str,
+    /// The buffer the switcher has been opened from.
+    pub(super) current: Option<&'a BufferId>,
+}
+
+#[derive(Debug)]
+pub(super) struct Scored {
+    pub(super) bucket: Bucket,
+    pub(super) components: Components,
+    pub(super) matched: Match,
+}
+
+impl Scored {
+    pub(super) fn nonmatch() -> Self {
+        Self {
+            bucket: Bucket::Nonmatch,
+            components: Components::default(),
+            matched: Match::None,
+        }
+    }
+}
+
+pub(super) trait Scorer: Debug {
+    /// Scores `buf` against a non-empty `pattern`.
+    fn score(&self, pattern: Pattern, buf: &Buffer, ctx: &Context) -> Scored;
+}
+
+pub(super) type BoxedScorer = Box<dyn Scorer + Send + Sync>;
+
+/// The scorer named `name`, or the best of the scorers named in a list.
+pub(super) fn from_value(value: &Value) -> Option<BoxedScorer> {
+    let by_name = |name: &Value| -> Option<BoxedScorer> {
+        match name.as_str()? {
+            "default" => Some(Box::new(DefaultScorer)),
+            "cwd" => Some(Box::new(CwdScorer)),
+            "others" => Some(Box::new(OthersScorer)),
+            _ => None,
+        }
+    };
+    match value {
+        Value::Array(names) if !names.is_empty() => names
+            .iter()
+            .map(by_name)
+            .collect::<Option<_>>()
+            .map(|scorers| Box::new(Best(scorers)) as BoxedScorer),
+        _ => by_name(value),
+    }
+}
+
+/// Takes the best result among the scorers.
+#[derive(Debug)]
+pub(super) struct Best(pub(super) Vec<BoxedScorer>);
+
+impl Scorer for Best {
+    fn score(&self, pattern: Pattern, buf: &Buffer, ctx: &Context) -> Scored {
+        self.0
+            .iter()
+            .map(|scorer| scorer.score(pattern, buf, ctx))
+            .max_by_key(|scored| (scored.bucket, scored.components.sum()))
+            .unwrap_or_else(Scored::nonmatch)
+    }
+}
+
+/// Scores as `DefaultScorer`, with a bonus for the files under the working directory.
+#[derive(Debug, Default)]
+pub(super) struct CwdScorer;
+
+impl Scorer for CwdScorer {
+    fn score(&self, pattern: Pattern, buf: &Buffer, ctx: &Context) -> Scored {
+        let mut scored = DefaultScorer.score(pattern, buf, ctx);
+        if scored.bucket != Bucket::Nonmatch && Uri::parse(&buf.path).is_under(ctx.cwd) {
+            scored.components.cwd = ctx.weights.cwd;
+        }
+        scored
+    }
+}
+
+/// Scores as `DefaultScorer`, except that the current buffer, which there is no point in
+/// switching to, never matches.
+#[derive(Debug, Default)]
+pub(super) struct OthersScorer;
+
+impl Scorer for OthersScorer {
+    fn score(&self, pattern: Pattern, buf: &Buffer, ctx: &Context) -> Scored {
+        if ctx.current == Some(&buf.id) {
+            return Scored::nonmatch();
+        }
+        DefaultScorer.score(pattern, buf, ctx)
+    }
+}
+
+/// Substring matches first and fuzzy matches next, both preferring matches close to the end of
+/// the name. Without a `/` in the pattern, matches in the basename are preferred.
+#[derive(Debug, Default)]
+pub(super) struct DefaultScorer;
+
+impl Scorer for DefaultScorer {
+    fn score(&self, pattern: Pattern, buf: &Buffer, ctx: &Context) -> Scored {
+        let weights = ctx.weights;
+        // Without a separator, the pattern is most likely a part of the file name.
+        let prefer_basename = !pattern.has_separator();
+
+        let mut tester = pattern.test(&buf.file);
+
+        match tester.next() {
+            Some(ControlFlow::Break(item)) => {
+                let bucket = if prefer_basename && buf.file.in_basename(&item.range) {
+                    Bucket::Basename
+                } else {
+                    Bucket::Substring
+                };
+                let bonus = if item.roffset == 0 {
+                    weights.end_with
+                } else {
+                    0
+                };
+                Scored {
+                    bucket,
+                    components: Components {
+                        end_with: bonus,
+                        offset: Weights::penalty(weights.offset, item.roffset),
+                        ..Components::default()
+                    },
+                    matched: Match::Sub(item.range),
+                }
+            }
+            Some(ControlFlow::Continue(item)) => {
+                let mut matched = vec![item.range];
+
+                for item in tester {
+                    match item {
+                        ControlFlow::Break(item) => {
+                            let components = Components {
+                                offset: Weights::penalty(weights.offset, item.roffset),
+                                run_length: Weights::penalty(
+                                    weights.run_length,
+                                    item.range.end - item.range.start,
+                                ),
+                                ..Components::default()
+                            };
+
+                            let bucket = if prefer_basename && buf.file.in_basename(&item.range) {
+                                Bucket::BasenameFuzzy
+                            } else {
+                                Bucket::Fuzzy
+                            };
+                            matched.push(item.range);
+
+                            return Scored {
+                                bucket,
+                                components,
+                                matched: Match::Fuzzy(matched),
+                            };
+                        }
+                        ControlFlow::Continue(item) => {
+                            matched.push(item.range);
+                        }
+                    }
+                }
+                Scored::nonmatch()
+            }
+            None => Scored::nonmatch(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(Bucket);
+
+    impl Scorer for Fixed {
+        fn score(&self, _pattern: Pattern, _buf: &Buffer, _ctx: &Context) -> Scored {
+            Scored {
+                bucket: self.0,
+                components: Components::default(),
+                matched: Match::None,
+            }
+        }
+    }
+
+    fn context<'a>(history: &'a History, weights: &'a Weights) -> Context<'a> {
+        Context {
+            history,
+            now: 0,
+            weights,
+            demote_stale: false,
+            cwd: "/p",
+            current: None,
+        }
+    }
+
+    #[test]
+    fn best() {
+        let buf = Buffer::for_test(1, "src/lib.rs");
+        let history = History::default();
+        let weights = Weights::default();
+        let ctx = context(&history, &weights);
+        let pattern = Pattern::from_str("src");
+
+        let scorer = Best(vec![
+            Box::new(DefaultScorer),
+            Box::new(Fixed(Bucket::Fuzzy)),
+        ]);
+        assert_eq!(scorer.score(pattern, &buf, &ctx).bucket, Bucket::Substring);
+
+        let scorer = Best(vec![
+            Box::new(DefaultScorer),
+            Box::new(Fixed(Bucket::Basename)),
+        ]);
+        assert_eq!(scorer.score(pattern, &buf, &ctx).bucket, Bucket::Basename);
+
+        let scorer = Best(Vec::new());
+        assert_eq!(scorer.score(pattern, &buf, &ctx).bucket, Bucket::Nonmatch);
+    }
+
+    #[test]
+    fn cwd_and_others() {
+        let inside = Buffer::for_test(1, "/p/lib.rs");
+        let outside = Buffer::for_test(2, "/q/lib.rs");
+        let history = History::default();
+        let weights = Weights::default();
+        let current = inside.id.clone();
+        let ctx = Context {
+            current: Some(&current),
+            ..context(&history, &weights)
+        };
+        let pattern = Pattern::from_str("lib");
+        let score = |scorer: &dyn Scorer, buf| {
+            let scored = scorer.score(pattern, buf, &ctx);
+            (scored.bucket, scored.components.cwd)
+        };
+
+        assert_eq!(score(&CwdScorer, &inside), (Bucket::Basename, weights.cwd));
+        assert_eq!(score(&CwdScorer, &outside), (Bucket::Basename, 0));
+        assert_eq!(score(&OthersScorer, &inside), (Bucket::Nonmatch, 0));
+        assert_eq!(score(&OthersScorer, &outside), (Bucket::Basename, 0));
+
+        let best = from_value(&Value::Array(vec![
+            Value::from("others"),
+            Value::from("cwd"),
+        ]))
+        .unwrap();
+        assert_eq!(score(&*best, &inside), (Bucket::Basename, weights.cwd));
+        assert!(from_value(&Value::from("others")).is_some());
+        assert!(from_value(&Value::from("unknown")).is_none());
+        assert!(from_value(&Value::Array(Vec::new())).is_none());
+    }
+
+    #[test]
+    fn penalty() {
+        assert_eq!(Weights::penalty(2, 3), -6);
+        assert_eq!(Weights::penalty(-2, 3), 6);
+        assert_eq!(Weights::penalty(i64::MIN, 3), i64::MAX);
+        assert_eq!(Weights::penalty(i64::MAX, usize::MAX), -i64::MAX);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::buffer_list::{Buffer, BufferList};
+use crate::project;
+
+use std::collections::HashMap;
+
+pub(super) const ORPHANED: &str = "orphaned";
+pub(super) const CLOSED: &str = "closed";
+/// The sections listed last, in this order, after the sections of the tabs.
+pub(super) const TRAILING: [&str; 2] = [ORPHANED, CLOSED];
+
+/// How the buffers are grouped into the sections of the results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Grouping {
+    /// The current tab, and the other tabs.
+    #[default]
+    Tab,
+    /// All buffers in one section.
+    All,
+    /// One section per directory, the most recently used first.
+    Directory,
+    /// One section per project, the one with the best match first.
+    Project,
+}
+
+impl Grouping {
+    pub(super) fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "tab" => Some(Self::Tab),
+            "all" => Some(Self::All),
+            "directory" => Some(Self::Directory),
+            "project" => Some(Self::Project),
+            _ => None,
+        }
+    }
+}
+
+/// A titled part of the results, ranked independently of the others.
+#[derive(Debug)]
+pub(super) struct Section<'a> {
+    /// Identifies the section across requests.
+    pub(super) name: String,
+    pub(super) title: String,
+    pub(super) buffers: Vec<&'a Buffer>,
+    /// Keeps the order of `buffers` instead of ranking them.
+    pub(super) keep_order: bool,
+}
+
+impl<'a> Section<'a> {
+    fn new(name: &str, title: &str, buffers: impl IntoIterator<Item = &'a Buffer>) -> Self {
+        Self {
+            name: name.to_string(),
+            title: title.to_string(),
+            buffers: buffers.into_iter().collect(),
+            keep_order: false,
+        }
+    }
+
+    /// The pinned buffers, in the order of the pins.
+    pub(super) fn pinned(buffers: Vec<&'a Buffer>) -> Self {
+        Self {
+            keep_order: true,
+            ..Self::new("pinned", "Pinned", buffers)
+        }
+    }
+
+    /// The buffers still loaded after the tabs they were open in have been closed.
+    pub(super) fn orphaned(buffers: &'a BufferList) -> Self {
+        Self::new(ORPHANED, "Orphaned", buffers)
+    }
+
+    /// The buffers recently closed in the current tab.
+    pub(super) fn closed(buffers: &'a BufferList) -> Self {
+        Self::new(CLOSED, "Recently closed", buffers)
+    }
+}
+
+pub(super) fn group<'a>(
+    grouping: Grouping,
+    current_tab: &'a BufferList,
+    other_tabs: &'a BufferList,
+) -> Vec<Section<'a>> {
+    match grouping {
+        Grouping::Tab => vec![
+            Section::new("current_tab", "Current Tab", current_tab),
+            Section::new("other_tabs", "Other Tabs", other_tabs),
+        ],
+        Grouping::All => vec![Section::new(
+            "all",
+            "All Tabs",
+            current_tab.into_iter().chain(other_tabs),
+        )],
+        Grouping::Directory => {
+            let mut dirs: HashMap<&str, Vec<&Buffer>> = HashMap::new();
+            for buf in current_tab.into_iter().chain(other_tabs) {
+                let dir = &buf.file.display_name()[buf.file.dir_span()];
+                dirs.entry(dir).or_default().push(buf);
+            }
+
+            let mut dirs = dirs.into_iter().collect::<Vec<_>>();
+            let last_used = |buffers: &[&Buffer]| buffers.iter().map(|buf| buf.last_used).max();
+            dirs.sort_unstable_by(|(lhs, lhs_buffers), (rhs, rhs_buffers)| {
+                last_used(rhs_buffers)
+                    .cmp(&last_used(lhs_buffers))
+                    .then_with(|| lhs.cmp(rhs))
+            });
+
+            dirs.into_iter()
+                .map(|(dir, buffers)| Section {
+                    name: format!("directory:{dir}"),
+                    title: if dir.is_empty() { "Other" } else { dir }.to_string(),
+                    buffers,
+                    keep_order: false,
+                })
+                .collect()
+        }
+        Grouping::Project => {
+            let mut projects: HashMap<Option<&str>, Vec<&Buffer>> = HashMap::new();
+            for buf in current_tab.into_iter().chain(other_tabs) {
+                projects
+                    .entry(buf.project.as_deref())
+                    .or_default()
+                    .push(buf);
+            }
+
+            // Ordered by the ranking later.
+            projects
+                .into_iter()
+                .map(|(root, buffers)| Section {
+                    name: format!("project:{}", root.unwrap_or_default()),
+                    title: root.map(project::title).unwrap_or("Other").to_string(),
+                    buffers,
+                    keep_order: false,
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(id: i64, file: &str, last_used: u64) -> Buffer {
+        Buffer {
+            last_used,
+            ..Buffer::for_test(id, file)
+        }
+    }
+
+    #[test]
+    fn directory() {
+        let current_tab = [
+            buffer(1, "src/lib.rs", 10),
+            buffer(2, "README.md", 20),
+            buffer(3, "src/main.rs", 30),
+        ]
+        .into_iter()
+        .collect::<BufferList>();
+        let other_tabs = [buffer(4, "lua/init.lua", 20)]
+            .into_iter()
+            .collect::<BufferList>();
+
+        let sections = group(Grouping::Directory, &current_tab, &other_tabs)
+            .into_iter()
+            .map(|section| {
+                let paths = section
+                    .buffers
+                    .iter()
+                    .map(|buf| buf.path.as_str())
+                    .collect::<Vec<_>>();
+                (section.title, paths)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            [
+                ("src/".to_string(), vec!["src/lib.rs", "src/main.rs"]),
+                ("Other".to_string(), vec!["README.md"]),
+                ("lua/".to_string(), vec!["lua/init.lua"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::io;
+use std::time::UNIX_EPOCH;
+
+/// How the file of a buffer differs on disk from when the buffer was loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Stale {
+    /// The file has been deleted or moved.
+    Deleted,
+    Changed,
+}
+
+impl Stale {
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Deleted => "deleted",
+            Self::Changed => "changed",
+        }
+    }
+
+    /// `loaded_mtime` is the modification time of the file, in seconds since the Unix epoch,
+    /// when the buffer was last loaded or written. A buffer without it, whose file did not
+    /// exist then, is not stale.
+    pub(super) fn check(path: &str, loaded_mtime: Option<u64>) -> Option<Self> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return loaded_mtime.is_some().then_some(Self::Deleted);
+            }
+            Err(_) => return None,
+        };
+        let mtime = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        loaded_mtime
+            .is_some_and(|loaded_mtime| loaded_mtime != mtime)
+            .then_some(Self::Changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn check() {
+        let dir = TempDir::new("stale");
+        let file = dir.join("a.rs");
+        fs::write(&file, "").unwrap();
+        let path = file.to_str().unwrap();
+
+        let mtime = fs::metadata(path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert_eq!(Stale::check(path, Some(mtime)), None);
+        assert_eq!(Stale::check(path, None), None);
+        assert_eq!(Stale::check(path, Some(mtime - 10)), Some(Stale::Changed));
+
+        fs::rename(&file, dir.join("b.rs")).unwrap();
+        assert_eq!(Stale::check(path, Some(mtime)), Some(Stale::Deleted));
+        // A new file not written yet
+        assert_eq!(Stale::check(path, None), None);
+    }
+}
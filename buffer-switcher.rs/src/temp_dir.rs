@@ -0,0 +1,33 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory for the files of a test, removed when dropped, even if the test fails.
+#[derive(Debug)]
+pub(super) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// The directory is named after `name`, which must be unique among the tests.
+    pub(super) fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("buffer-switcher-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // Resolved, as the names of the files in it are compared with the resolved ones.
+        Self(dir.canonicalize().unwrap())
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
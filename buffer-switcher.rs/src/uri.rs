@@ -0,0 +1,314 @@
+use crate::pattern::Target;
+
+use nvim_router::nvim_rs::Value;
+
+pub(super) struct Uri<'a> {
+    name: &'a str,
+    kind: Kind<'a>,
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum Kind<'a> {
+    File(&'a str),
+    Oil(&'a str),
+    Fugitive { rev: &'a str, path: &'a str },
+    Term { cmd: &'a str },
+    Scp { host: &'a str, path: &'a str },
+    Health,
+    Other { scheme: &'a str, rest: &'a str },
+}
+
+impl<'a> Kind<'a> {
+    fn parse(name: &'a str) -> Self {
+        let Some((scheme, rest)) = name.split_once("://") else {
+            return Self::File(name);
+        };
+        if scheme.is_empty()
+            || !scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        {
+            return Self::File(name);
+        }
+
+        // The parts missing from a malformed name are empty subslices of it, to keep their
+        // offsets in the name.
+        match scheme {
+            "file" => Self::File(rest),
+            "oil" => Self::Oil(rest),
+            // fugitive:///repo/.git//{rev}/{path}
+            "fugitive" => {
+                let (_, object) = rest.split_once("//").unwrap_or((rest, end(rest)));
+                let (rev, path) = object.split_once('/').unwrap_or((object, end(object)));
+                Self::Fugitive { rev, path }
+            }
+            // term://{cwd}//{pid}:{cmd}
+            "term" => {
+                let (_, job) = rest.split_once("//").unwrap_or((&rest[..0], rest));
+                let (_, cmd) = job.split_once(':').unwrap_or((&job[..0], job));
+                Self::Term { cmd }
+            }
+            // scp://{host}//{absolute path} or scp://{host}/{relative path}
+            "scp" => {
+                let (host, path) = rest.split_once('/').unwrap_or((rest, end(rest)));
+                Self::Scp { host, path }
+            }
+            "health" => Self::Health,
+            _ => Self::Other { scheme, rest },
+        }
+    }
+}
+
+impl<'a> Uri<'a> {
+    pub(super) fn parse(name: &'a str) -> Self {
+        Self {
+            name,
+            kind: Kind::parse(name),
+        }
+    }
+
+    /// The path of the file or directory on this machine, if the buffer is one.
+    pub(super) fn local_path(&self) -> Option<&'a str> {
+        match self.kind {
+            Kind::File(path) | Kind::Oil(path) if path.starts_with('/') => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Whether the buffer is a file or directory under `dir` on this machine.
+    pub(super) fn is_under(&self, dir: &str) -> bool {
+        self.local_path()
+            .is_some_and(|path| strip_dir(path, dir).is_some())
+    }
+
+    /// The path of the file on this machine, if the buffer is a plain file.
+    pub(super) fn file_path(&self) -> Option<&'a str> {
+        match self.kind {
+            Kind::File(path) if path.starts_with('/') => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Byte offset of `part`, which must be a subslice of the name.
+    fn offset(&self, part: &str) -> usize {
+        part.as_ptr() as usize - self.name.as_ptr() as usize
+    }
+
+    fn push_original(&self, target: &mut Target, part: &str) {
+        target.push_original(part, self.offset(part));
+    }
+
+    fn push_path(&self, target: &mut Target, path: &str, cwd: &str, home_dir: &str) {
+        if let Some(rest) = strip_dir(path, cwd) {
+            target.push_replacement(".", self.offset(path)..self.offset(rest));
+            self.push_original(target, rest);
+        } else if let Some(rest) = strip_dir(path, home_dir) {
+            target.push_replacement("~", self.offset(path)..self.offset(rest));
+            self.push_original(target, rest);
+        } else {
+            self.push_original(target, path);
+        }
+    }
+
+    pub(super) fn to_target(&self, cwd: &str, home_dir: &str) -> Target {
+        let mut target = Target::with_capacity(self.name.len() + 8);
+
+        match self.kind {
+            Kind::File(path) => {
+                self.push_path(&mut target, path, cwd, home_dir);
+            }
+            Kind::Oil(path) => {
+                target.push_replacement("[oil] ", 0..self.offset(path));
+                self.push_path(&mut target, path, cwd, home_dir);
+            }
+            Kind::Fugitive { rev, path } => {
+                target.push_replacement("[git:", 0..self.offset(rev));
+                self.push_original(&mut target, short_rev(rev));
+                target.push(']');
+                if !path.is_empty() {
+                    target.push(' ');
+                    self.push_original(&mut target, path);
+                }
+            }
+            Kind::Term { cmd } => {
+                target.push_replacement("[term] ", 0..self.offset(cmd));
+                self.push_original(&mut target, cmd);
+            }
+            Kind::Scp { host, path } => {
+                target.push_replacement("[scp:", 0..self.offset(host));
+                self.push_original(&mut target, host);
+                target.push(']');
+                if !path.is_empty() {
+                    target.push(' ');
+                    self.push_original(&mut target, path);
+                }
+            }
+            Kind::Health => {
+                target.push_replacement("[health]", 0..self.name.len());
+            }
+            Kind::Other { scheme, rest } => {
+                target.push('[');
+                self.push_original(&mut target, scheme);
+                target.push(']');
+                if !rest.is_empty() {
+                    target.push(' ');
+                    self.push_original(&mut target, rest);
+                }
+            }
+        }
+
+        target.split_basename();
+        target
+    }
+}
+
+pub(super) fn no_name(id: &Value, first_line: Option<&str>) -> Target {
+    let first_line = first_line.map(str::trim).unwrap_or_default();
+
+    let mut target = Target::with_capacity(first_line.len() + 16);
+    target.push_str("[No Name] #");
+    if let Some(id) = id.as_i64() {
+        target.push_str(&id.to_string());
+    } else if let Some(id) = id.as_str() {
+        target.push_str(id);
+    }
+    if !first_line.is_empty() {
+        target.push(' ');
+        target.push_str(first_line);
+    }
+    target
+}
+
+/// The empty slice at the end of `s`.
+fn end(s: &str) -> &str {
+    &s[s.len()..]
+}
+
+fn short_rev(rev: &str) -> &str {
+    if rev.len() > 7 && rev.bytes().all(|b| b.is_ascii_hexdigit()) {
+        &rev[..7]
+    } else {
+        rev
+    }
+}
+
+fn strip_dir<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    if dir.is_empty() {
+        return None;
+    }
+    let dir = dir.strip_suffix('/').unwrap_or(dir);
+    let rest = path.strip_prefix(dir)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expect_display(name: &str, expected: &str) {
+        let target = Uri::parse(name).to_target("/home/me/proj", "/home/me");
+        assert_eq!(target.display_name(), expected);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Kind::parse("/a/b.rs"), Kind::File("/a/b.rs"));
+        assert_eq!(Kind::parse("file:///a/b.rs"), Kind::File("/a/b.rs"));
+        assert_eq!(Kind::parse("/a/x://y"), Kind::File("/a/x://y"));
+        assert_eq!(Kind::parse("oil:///a/"), Kind::Oil("/a/"));
+        assert_eq!(
+            Kind::parse("fugitive:///repo/.git//0/src/lib.rs"),
+            Kind::Fugitive {
+                rev: "0",
+                path: "src/lib.rs"
+            }
+        );
+        assert_eq!(
+            Kind::parse("term://~/proj//1234:/bin/zsh"),
+            Kind::Term { cmd: "/bin/zsh" }
+        );
+        assert_eq!(
+            Kind::parse("scp://host//etc/hosts"),
+            Kind::Scp {
+                host: "host",
+                path: "/etc/hosts"
+            }
+        );
+        assert_eq!(Kind::parse("health://"), Kind::Health);
+        assert_eq!(
+            Kind::parse("man://ls(1)"),
+            Kind::Other {
+                scheme: "man",
+                rest: "ls(1)"
+            }
+        );
+    }
+
+    #[test]
+    fn file_display() {
+        expect_display("/home/me/proj/src/lib.rs", "./src/lib.rs");
+        expect_display("/home/me/project/lib.rs", "~/project/lib.rs");
+        expect_display("/home/me/.vimrc", "~/.vimrc");
+        expect_display("/etc/hosts", "/etc/hosts");
+    }
+
+    #[test]
+    fn scheme_display() {
+        expect_display("oil:///home/me/proj/src/", "[oil] ./src/");
+        expect_display("oil:///tmp/", "[oil] /tmp/");
+        expect_display(
+            "fugitive:///home/me/proj/.git//0/src/lib.rs",
+            "[git:0] src/lib.rs",
+        );
+        expect_display(
+            "fugitive:///home/me/proj/.git//0123456789abcdef0123456789abcdef01234567",
+            "[git:0123456]",
+        );
+        expect_display("term://~//1234:zsh", "[term] zsh");
+        expect_display("scp://host//etc/hosts", "[scp:host] /etc/hosts");
+        expect_display("health://", "[health]");
+        expect_display("man://ls(1)", "[man] ls(1)");
+    }
+
+    #[test]
+    fn original_range() {
+        let name = "fugitive:///home/me/proj/.git//0/src/lib.rs";
+        let target = Uri::parse(name).to_target("/home/me/proj", "/home/me");
+        let range = target.original_range(8..18);
+        assert_eq!(target.display_name()[8..18], name[range]);
+
+        let name = "oil:///home/me/proj/src/";
+        let target = Uri::parse(name).to_target("/home/me/proj", "/home/me");
+        assert_eq!(target.original_range(0..7), 0..19);
+        assert_eq!(target.original_range(7..12), 19..24);
+    }
+
+    #[test]
+    fn malformed() {
+        for (name, expected) in [
+            ("fugitive://", "[git:]"),
+            ("fugitive://x", "[git:]"),
+            ("fugitive:///repo/.git//", "[git:]"),
+            ("term://", "[term] "),
+            ("scp://host", "[scp:host]"),
+        ] {
+            let target = Uri::parse(name).to_target("/home/me/proj", "/home/me");
+            assert_eq!(target.display_name(), expected);
+            let range = target.original_range(0..target.len());
+            assert!(range.end <= name.len(), "{name}: {range:?}");
+        }
+    }
+
+    #[test]
+    fn no_name_display() {
+        let target = no_name(&Value::from(7), None);
+        assert_eq!(target.display_name(), "[No Name] #7");
+
+        let target = no_name(&Value::from(7), Some("  fn main() {"));
+        assert_eq!(target.display_name(), "[No Name] #7 fn main() {");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::States;
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use tokio::sync::Mutex;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+
+/// Watches the directories of the open files, and updates whether their buffers are stale as
+/// soon as the files change on disk.
+#[derive(Debug)]
+pub(super) struct Watcher {
+    watcher: RecommendedWatcher,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Events are handled on the thread of the watcher, which stops when `states` is dropped.
+    pub(super) fn new(states: Weak<Mutex<States>>) -> notify::Result<Self> {
+        Self::with_handled(states, || ())
+    }
+
+    /// Calls `handled` after each event has been handled.
+    fn with_handled(
+        states: Weak<Mutex<States>>,
+        handled: impl Fn() + Send + 'static,
+    ) -> notify::Result<Self> {
+        let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if event.kind.is_access() {
+                return;
+            }
+            let Some(states) = states.upgrade() else {
+                return;
+            };
+
+            {
+                let mut lock = states.blocking_lock();
+                for path in &event.paths {
+                    if let Some(path) = path.to_str() {
+                        lock.file_changed(path);
+                    }
+                }
+            }
+            handled();
+        })?;
+
+        Ok(Self {
+            watcher,
+            dirs: BTreeSet::new(),
+        })
+    }
+
+    /// Watches exactly `dirs`. Must not be called with the lock of the states held, since the
+    /// watcher waits for the event being handled.
+    pub(super) fn watch(&mut self, dirs: BTreeSet<PathBuf>) {
+        for dir in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            let _ = self.watcher.watch(dir, RecursiveMode::NonRecursive);
+        }
+        self.dirs = dirs;
+    }
+}
+
+/// Starts the watcher on the first call.
+pub(super) fn watch(
+    watcher: &mut Option<Watcher>,
+    states: &Arc<Mutex<States>>,
+    dirs: BTreeSet<PathBuf>,
+) {
+    if watcher.is_none() {
+        *watcher = Watcher::new(Arc::downgrade(states)).ok();
+    }
+    if let Some(watcher) = watcher {
+        watcher.watch(dirs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stale::Stale;
+    use crate::temp_dir::TempDir;
+
+    use nvim_router::nvim_rs::Value;
+
+    use std::fs;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn stale_on_change() {
+        let dir = TempDir::new("watcher");
+        let file = dir.join("a.rs");
+        fs::write(&file, "").unwrap();
+        let path = file.to_str().unwrap();
+        let mtime = fs::metadata(path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let states = Arc::new(Mutex::new(States::default()));
+        let dirs = {
+            let mut lock = states.blocking_lock();
+            lock.update(
+                vec![Value::Array(vec![
+                    Value::from(1),
+                    Value::from(path),
+                    Value::Map(Vec::new()),
+                    Value::Map(vec![(Value::from("mtime"), Value::from(mtime))]),
+                ])],
+                Vec::new(),
+                Vec::new(),
+                "/",
+            );
+            lock.watched_dirs()
+        };
+        assert_eq!(dirs, BTreeSet::from([dir.to_path_buf()]));
+
+        let (sender, handled) = mpsc::sync_channel(1);
+        let mut watcher = Watcher::with_handled(Arc::downgrade(&states), move || {
+            let _ = sender.try_send(());
+        })
+        .unwrap();
+        watcher.watch(dirs);
+        let stale = || {
+            let lock = states.blocking_lock();
+            lock.find_buffer(path).and_then(|buf| buf.stale)
+        };
+        assert_eq!(stale(), None);
+
+        fs::remove_file(&file).unwrap();
+        while stale().is_none() {
+            handled.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert_eq!(stale(), Some(Stale::Deleted));
+    }
+}
@@ -7,6 +7,8 @@ local ns = api.nvim_create_namespace("NaughieBufferSwitcherHl")
 local default_hl = {
     cursor = { link = "CursorLine" },
     matched = { link = "Search" },
+    directory = { link = "Comment" },
+    stale = { link = "DiagnosticWarn" },
     frame = { link = "FloatBorder" },
     frame_title = { link = "Normal" },
 }
@@ -14,6 +16,8 @@ local default_hl = {
 local hl_names = {
     cursor = "BufferSwitcherCursor",
     matched = "BufferSwitcherMatched",
+    directory = "BufferSwitcherDirectory",
+    stale = "BufferSwitcherStale",
     frame = "BufferSwitcherFrame",
     frame_title = "BufferSwitcherFrameTitle",
 }
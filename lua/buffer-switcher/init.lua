@@ -63,6 +63,7 @@ function M.setup(opts)
     if opts.border then
         ui.update_opts({ background = opts.border })
     end
+    ui.configure({ select_all = opts.select_all })
 
     ls.on_startup()
 
@@ -72,6 +73,50 @@ function M.setup(opts)
     hl.set_highlight_groups(opts.hl)
 
     rpc.register(opts.plugin_dir, opts.rpc_ns)
+
+    local history_path = opts.history_path
+    if history_path == nil then
+        history_path = vim.fn.stdpath("data") .. "/buffer-switcher/history"
+    end
+    if history_path then
+        rpc.call.load_history(history_path)
+    end
+
+    local pins_path = opts.pins_path
+    if pins_path == nil then
+        pins_path = vim.fn.stdpath("data") .. "/buffer-switcher/pins"
+    end
+    if pins_path then
+        rpc.call.load_pins(pins_path)
+    end
+
+    local ranking_config = {
+        weights = opts.weights,
+        scorer = opts.scorer,
+        group_by = opts.group_by,
+        dedupe = opts.dedupe,
+        prefer_tab = opts.prefer_tab,
+        demote_stale = opts.demote_stale,
+    }
+    if next(ranking_config) then
+        rpc.call.configure(ranking_config)
+    end
+end
+
+-- file: { path, buf_id }, where buf_id may be nil or no longer valid
+local function open_file(file)
+    if not file or file == vim.NIL then return end
+
+    if file.buf_id and api.nvim_buf_is_valid(file.buf_id) then
+        api.nvim_set_current_buf(file.buf_id)
+    else
+        vim.cmd.edit(vim.fn.fnameescape(file.path))
+    end
+end
+
+-- Opens the file in the numbered slot (1-9) of the current tab without opening the list.
+function M.jump_to_slot(n)
+    open_file(rpc.call.slot(n, api.nvim_get_current_tabpage()))
 end
 
 M.fn = {
@@ -79,7 +124,9 @@ M.fn = {
         local buffers = ls.get_buffers()
         rpc.call.update_buffers(buffers)
 
-        ui.open_results(buffers)
+        local sections = rpc.call.rerank("")
+        ui.open_results(sections)
+        ui.render_results(sections)
         ui.open_input(function(buf)
             local text_changed = api.nvim_create_augroup("NaughieBufferSwitcherTextChanged", { clear = true })
             api.nvim_create_autocmd("TextChangedI", {
@@ -97,11 +144,35 @@ M.fn = {
         end)
     end,
 
+    -- Pin the current buffer in the current tab, to list it first in a fixed order.
+    pin = function()
+        local file = api.nvim_buf_get_name(0)
+        if file == "" then return end
+        rpc.call.pin(file, api.nvim_get_current_tabpage())
+    end,
+
+    unpin = function()
+        local file = api.nvim_buf_get_name(0)
+        if file == "" then return end
+        rpc.call.unpin(file, api.nvim_get_current_tabpage())
+    end,
+
+    -- Reopen the buffer most recently closed in the current tab.
+    reopen_last = function()
+        rpc.call.update_buffers(ls.get_buffers())
+        open_file(rpc.call.reopen_last(api.nvim_get_current_tabpage()))
+    end,
+
     open_selected_buf = ui.open_selected_buf,
     select_next = ui.select_next,
     select_prev = ui.select_prev,
+    explain_selected = ui.explain_selected,
 
     close = ui.close,
 }
 
+for n = 1, 9 do
+    M.fn["slot_" .. n] = function() M.jump_to_slot(n) end
+end
+
 return M
@@ -1,25 +1,103 @@
 local M = {}
 
 local mkstate = require("glocal-states")
+local rpc = require("buffer-switcher.rpc")
 
 local buffers = mkstate.tab()
+-- The same tables as in `buffers` by tab handle, kept to report the buffers of a closed tab.
+local known_tabs = {}
 
 local api = vim.api
 
+-- Unnamed buffers are keyed by their buffer id instead of the file name.
+local function unnamed_key(buf_id)
+    return buf_id
+end
+
+local function is_unnamed_key(key)
+    return type(key) == "number"
+end
+
+local function first_line(buf_id)
+    for _, line in ipairs(api.nvim_buf_get_lines(buf_id, 0, 100, false)) do
+        if line:find("%S") then return line end
+    end
+end
+
+-- Remembers when the file was modified as of loading or writing the buffer, to tell whether it
+-- has changed on disk since.
+local function record_mtime(buf_id)
+    local stat = vim.uv.fs_stat(api.nvim_buf_get_name(buf_id))
+    vim.b[buf_id].buffer_switcher_mtime = stat and stat.mtime.sec
+end
+
+local function buf_info(buf_id, focus)
+    local info = {
+        current = buf_id == focus.current,
+        alternate = buf_id == focus.alternate,
+        mtime = vim.b[buf_id].buffer_switcher_mtime,
+    }
+
+    local bufinfo = vim.fn.getbufinfo(buf_id)[1]
+    if bufinfo then
+        info.lastused = bufinfo.lastused
+    end
+
+    return info
+end
+
+local function buf_item(key, buf_id, tab, focus)
+    if not is_unnamed_key(key) then
+        return { buf_id, key, { tab }, buf_info(buf_id, focus) }
+    end
+
+    if not api.nvim_buf_is_valid(buf_id) or api.nvim_buf_get_name(buf_id) ~= "" then return end
+
+    local info = buf_info(buf_id, focus)
+    info.first_line = first_line(buf_id)
+    return { buf_id, "", { tab }, info }
+end
+
+local function current_buffers()
+    local buffers_in_tab = buffers.get()
+    if not buffers_in_tab then
+        buffers_in_tab = {}
+        buffers.set(buffers_in_tab)
+    end
+    known_tabs[api.nvim_get_current_tabpage()] = buffers_in_tab
+    return buffers_in_tab
+end
+
 function M.get_buffers()
     local current_tab_id = api.nvim_get_current_tabpage()
+    local focus = {
+        current = api.nvim_get_current_buf(),
+        alternate = vim.fn.bufnr("#"),
+    }
 
     local buf_current = {}
     local buf_other = {}
+    local in_tabs = {}
 
     for tab, buffers_in_tab in buffers.iter() do
-        if tab == current_tab_id then
-            for file, buf_id in pairs(buffers_in_tab) do
-                table.insert(buf_current, { buf_id, file, { tab } })
+        local list = tab == current_tab_id and buf_current or buf_other
+        for key, buf_id in pairs(buffers_in_tab) do
+            in_tabs[buf_id] = true
+            local item = buf_item(key, buf_id, tab, focus)
+            if item then
+                table.insert(list, item)
             end
-        else
-            for file, buf_id in pairs(buffers_in_tab) do
-                table.insert(buf_other, { buf_id, file, { tab } })
+        end
+    end
+
+    -- Loaded but in no tab, such as the buffers of closed tabs
+    local buf_detached = {}
+    for _, buf_id in ipairs(api.nvim_list_bufs()) do
+        if not in_tabs[buf_id] and api.nvim_buf_is_loaded(buf_id) then
+            local name = api.nvim_buf_get_name(buf_id)
+            local item = buf_item(name ~= "" and name or unnamed_key(buf_id), buf_id, nil, focus)
+            if item then
+                table.insert(buf_detached, item)
             end
         end
     end
@@ -27,6 +105,7 @@ function M.get_buffers()
     return {
         current_tab = buf_current,
         other_tabs = buf_other,
+        detached = buf_detached,
     }
 end
 
@@ -38,19 +117,18 @@ local function update_with_api()
             local name = api.nvim_buf_get_name(buf)
             if name ~= "" then
                 current_bufs[name] = buf
+                record_mtime(buf)
+            elseif api.nvim_get_option_value("buflisted", { buf = buf }) then
+                current_bufs[unnamed_key(buf)] = buf
             end
         end
     end
 
     if not next(current_bufs) then return end
 
-    local buffers_in_tab = buffers.get()
-    if buffers_in_tab then
-        for name, buf_id in pairs(current_bufs) do
-            buffers_in_tab[name] = buf_id
-        end
-    else
-        buffers.set(current_bufs)
+    local buffers_in_tab = current_buffers()
+    for name, buf_id in pairs(current_bufs) do
+        buffers_in_tab[name] = buf_id
     end
 end
 
@@ -60,17 +138,94 @@ function M.autocmd()
     api.nvim_create_autocmd("BufEnter", {
         group = augroup,
         callback = function(ev)
-            local file = ev.file
+            local file = api.nvim_buf_get_name(ev.buf)
             local buf_id = ev.buf
 
             local listed = api.nvim_get_option_value("buflisted", { buf = buf_id })
-            if not file or file == "" or not listed then return end
+            if not listed then return end
+            if not file or file == "" then
+                file = unnamed_key(buf_id)
+            else
+                rpc.call.buffer_entered(file)
+            end
+
+            current_buffers()[file] = buf_id
+        end,
+    })
+
+    api.nvim_create_autocmd({ "BufReadPost", "BufWritePost", "FileChangedShellPost" }, {
+        group = augroup,
+        callback = function(ev)
+            record_mtime(ev.buf)
+        end,
+    })
+
+    api.nvim_create_autocmd("TabClosed", {
+        group = augroup,
+        callback = function()
+            for tab, buffers_in_tab in pairs(known_tabs) do
+                if not api.nvim_tabpage_is_valid(tab) then
+                    known_tabs[tab] = nil
+
+                    local items = {}
+                    for _, buf_id in pairs(buffers_in_tab) do
+                        if api.nvim_buf_is_loaded(buf_id) then
+                            table.insert(items, { buf_id, api.nvim_buf_get_name(buf_id) })
+                        end
+                    end
+                    rpc.call.tab_closed(tab, items)
+                end
+            end
+        end,
+    })
+
+    api.nvim_create_autocmd("BufDelete", {
+        group = augroup,
+        callback = function(ev)
+            local file = api.nvim_buf_get_name(ev.buf)
+            local key = file ~= "" and file or unnamed_key(ev.buf)
+
+            local tabs = {}
+            for tab, buffers_in_tab in buffers.iter() do
+                if buffers_in_tab[key] == ev.buf then
+                    buffers_in_tab[key] = nil
+                    table.insert(tabs, tab)
+                end
+            end
+
+            -- Also sent for a buffer in no tab, which may be orphaned.
+            rpc.call.buffer_removed(file, ev.buf, tabs)
+        end,
+    })
 
-            local buffers_in_tab = buffers.get()
-            if buffers_in_tab then
-                buffers_in_tab[file] = buf_id
+    api.nvim_create_autocmd("BufFilePre", {
+        group = augroup,
+        callback = function(ev)
+            vim.b[ev.buf].buffer_switcher_old_name = api.nvim_buf_get_name(ev.buf)
+        end,
+    })
+
+    api.nvim_create_autocmd("BufFilePost", {
+        group = augroup,
+        callback = function(ev)
+            local old_file = vim.b[ev.buf].buffer_switcher_old_name
+            vim.b[ev.buf].buffer_switcher_old_name = nil
+            local new_file = api.nvim_buf_get_name(ev.buf)
+            if not old_file or new_file == "" or old_file == new_file then return end
+
+            -- A scratch buffer given its first name is only known to the tabs by its number.
+            local old_key = old_file
+            if old_file == "" then
+                old_key = unnamed_key(ev.buf)
             else
-                buffers.set({ [file] = buf_id })
+                rpc.call.buffer_renamed(old_file, new_file)
+            end
+
+            for _, buffers_in_tab in buffers.iter() do
+                if buffers_in_tab[old_key] == ev.buf then
+                    buffers_in_tab[old_key] = nil
+                    buffers_in_tab[new_file] = ev.buf
+                end
             end
         end,
     })
@@ -26,10 +26,95 @@ end
 M.call = {
     update_buffers = function(buffers)
         local cwd = vim.uv.cwd()
-        rpc.notify("update_buffers", buffers.current_tab, buffers.other_tabs, cwd)
+        local tab = vim.api.nvim_get_current_tabpage()
+        rpc.notify(
+            "update_buffers",
+            buffers.current_tab,
+            buffers.other_tabs,
+            cwd,
+            { tab },
+            buffers.detached
+        )
     end,
 
-    rerank = function(input)
+    load_history = function(path)
+        rpc.notify("load_history", path)
+    end,
+
+    load_pins = function(path)
+        rpc.notify("load_pins", path)
+    end,
+
+    -- The pins of the current tab, given as tab, are saved under the working directory.
+    -- Returns whether the file has been newly pinned in the tab
+    pin = function(file, tab)
+        return rpc.request("pin", file, { tab }, vim.uv.cwd())
+    end,
+
+    -- Returns whether the file was pinned in the tab
+    unpin = function(file, tab)
+        return rpc.request("unpin", file, { tab }, vim.uv.cwd())
+    end,
+
+    -- Returns { path, buf_id } of the file in the numbered slot (1-9) of the tab, where buf_id
+    -- is nil if the file is not open, or nil if the slot is empty
+    slot = function(n, tab)
+        return rpc.request("slot", { n }, { tab }, vim.uv.cwd())
+    end,
+
+    -- Returns { path, buf_id } of the buffer most recently closed in the tab and not open again,
+    -- or nil if there is none
+    reopen_last = function(tab)
+        return rpc.request("reopen_last", { tab })
+    end,
+
+    -- tabs: the tabs the buffer is removed from
+    buffer_removed = function(file, buf_id, tabs)
+        rpc.notify("buffer_removed", file, { buf_id }, tabs)
+    end,
+
+    -- buffers: { { buf_id, name }, ... }, the buffers still loaded from the closed tab
+    tab_closed = function(tab, buffers)
+        rpc.notify("tab_closed", { tab }, buffers)
+    end,
+
+    buffer_renamed = function(old_file, new_file)
+        rpc.notify("buffer_renamed", old_file, new_file)
+    end,
+
+    buffer_entered = function(file)
+        rpc.notify("buffer_entered", file)
+    end,
+
+    selected = function(input, file)
+        rpc.notify("selected", input, file)
+    end,
+
+    -- Why the buffer ranks where it does for the input: the bucket, the score components, the
+    -- candidate match ranges and the buffers ranked above it with the reason.
+    explain = function(input, buf_id)
+        return rpc.request("explain", input, { buf_id })
+    end,
+
+    -- config:
+    --   weights: integers overriding the scoring constants, see `weights` in the README
+    --   scorer: "default", "cwd" or "others", or a list of them to take the best score of
+    --   group_by: "tab", "all", "directory" or "project", how the buffers are grouped into sections
+    --   dedupe: show a buffer open in several tabs once
+    --   prefer_tab: "current" or "other", which tab a deduplicated buffer is shown in and opened in
+    --   demote_stale: rank the buffers of files deleted or changed on disk after the others
+    configure = function(config)
+        rpc.request("configure", { config })
+    end,
+
+    -- Returns the sections of the results, in order: { { name, title, items }, ... }
+    -- opts (optional):
+    --   columns: also report char indices and display cells of highlight ranges
+    --   ambiwidth: "single" or "double", how East Asian Ambiguous characters are measured
+    rerank = function(input, opts)
+        if opts then
+            return rpc.request("rank", input, { opts })
+        end
         return rpc.request("rank", input)
     end,
 }
@@ -1,12 +1,18 @@
 local M = {}
 
 local hl = require("buffer-switcher.highlight")
+local rpc = require("buffer-switcher.rpc")
 
 local mkstate = require("glocal-states")
 local myui = require("my-ui")
 
 local states = {}
 
+local config = {
+    -- Let the selection move to the other tabs section of the "tab" grouping too.
+    select_all = false,
+}
+
 local api = vim.api
 
 local frame = {
@@ -14,12 +20,11 @@ local frame = {
     hor = "─",
     vert = "│",
     corners = { "╮", "╭", "╰", "╯" },
-
-    titles = {
-        " ꒰Current Tab ꒱",
-        " ꒰Other Tabs ꒱",
-    },
 }
+
+local function frame_title(title)
+    return string.format(" ꒰%s ꒱", title)
+end
 local frame_len = {
     hor = {
         len = string.len(frame.hor),
@@ -29,16 +34,6 @@ local frame_len = {
         len = string.len(frame.vert),
         width = vim.fn.strwidth(frame.vert),
     },
-
-    titles = {
-        max_width = (function()
-            local max_width = 0
-            for _, title in ipairs(frame.titles) do
-                max_width = math.max(max_width, vim.fn.strwidth(title))
-            end
-            return max_width
-        end)(),
-    },
 }
 
 local ui = myui.declare_ui({
@@ -65,11 +60,32 @@ local function render_buf_item(buf_item, start_line, max_width)
 
     local line = string.format("%s%s%s%s%s", frame.vert, left_pad, file_path, right_pad, frame.vert)
 
+    local left_pad_len = padding + frame_len.vert.len
+
+    -- The file has been deleted or changed on disk since the buffer was loaded.
+    local flags = buf_item[7]
+    if flags and flags.stale then
+        table.insert(ext, {
+            start_col = left_pad_len,
+            end_col = left_pad_len + string.len(file_path),
+            line = start_line,
+            hl = "stale",
+        })
+    end
+
+    local spans = buf_item[6]
+    if spans and spans.dir and spans.dir.end_idx > spans.dir.start_idx then
+        table.insert(ext, {
+            start_col = spans.dir.start_idx + left_pad_len,
+            end_col = spans.dir.end_idx + left_pad_len,
+            line = start_line,
+            hl = "directory",
+        })
+    end
+
     local matched = buf_item[4]
 
     if matched then
-        local left_pad_len = padding + frame_len.vert.len
-
         for _, range in ipairs(matched) do
             local start_idx = range.start_idx + left_pad_len
             local end_idx = range.end_idx + left_pad_len
@@ -144,56 +160,74 @@ end
 
 local function buf_item_table(buf_item)
     local matched = buf_item[4] ~= nil and #buf_item[4] > 0
-    return { buf = buf_item[1], tab = buf_item[3][1], matched = matched, rendered = buf_item[2] }
+    return {
+        buf = buf_item[1],
+        -- The preferred tab first
+        tab = buf_item[3][1],
+        matched = matched,
+        rendered = buf_item[2],
+        path = buf_item[5],
+    }
 end
 
-local function update_states(buffers)
+-- sections: { { name, title, items }, ... }, as returned by rpc.call.rerank
+local function update_states(sections)
     local calc_width = states.max_width == nil
 
-    local current_tab = {}
-    local other_tabs = {}
-
+    local items = {}
     local max_width = 0
 
-    for _, buf_item in ipairs(buffers.current_tab) do
-        table.insert(current_tab, buf_item_table(buf_item))
-        if calc_width then
-            max_width = math.max(max_width, vim.fn.strwidth(buf_item[2]))
+    -- Each section is framed by a line above and a line below its items.
+    local line = 0
+    for _, section in ipairs(sections) do
+        line = line + 1
+        for _, buf_item in ipairs(section.items) do
+            local item = buf_item_table(buf_item)
+            item.line = line
+            item.section = section.name
+            table.insert(items, item)
+            line = line + 1
+
+            if calc_width then
+                max_width = math.max(max_width, vim.fn.strwidth(buf_item[2]))
+            end
         end
-    end
+        line = line + 1
 
-    for _, buf_item in ipairs(buffers.other_tabs) do
-        table.insert(other_tabs, buf_item_table(buf_item))
         if calc_width then
-            max_width = math.max(max_width, vim.fn.strwidth(buf_item[2]))
+            max_width = math.max(max_width, vim.fn.strwidth(frame_title(section.title)))
         end
     end
 
     if calc_width then
-        max_width = math.max(max_width, frame_len.titles.max_width)
-
         local max_total_width = max_width + 2 * frame.padding
         local rem = max_total_width % frame_len.hor.width
         if rem ~= 0 then
             max_width = max_width + (frame_len.hor.width - rem)
         end
-    end
 
-    if calc_width then
         states.max_width = max_width
     end
 
-    states.items = {
-        current_tab = current_tab,
-        other_tabs = other_tabs,
-    }
+    -- The other tabs listed besides the current tab are not selected unless configured otherwise.
+    local selectable = {}
+    for _, item in ipairs(items) do
+        if config.select_all or item.section ~= "other_tabs" then
+            table.insert(selectable, item)
+        end
+    end
+
+    states.items = selectable
+    states.first_item = items[1]
+    states.first_section_len = sections[1] and #sections[1].items or 0
 
     states.selected = nil
-    if #current_tab == 1 then
+    if #selectable == 1 then
         states.selected = 1
-    elseif #current_tab > 1 then
-        local buf_item = current_tab[1]
-        if buf_item.matched then
+    elseif #selectable > 1 then
+        local buf_item = selectable[1]
+        -- With an empty input, the first item is the alternate buffer.
+        if buf_item.matched or M.get_input() == "" then
             states.selected = 1
         end
     end
@@ -206,7 +240,7 @@ local function set_selected_hl(buf_id)
     end
 
     if states.selected then
-        local buf_item = states.items.current_tab[states.selected]
+        local buf_item = states.items[states.selected]
 
         local padding = 2
         local start_col = frame_len.vert.len + frame.padding - padding
@@ -216,21 +250,22 @@ local function set_selected_hl(buf_id)
         states.selected_ext_id = hl.set_extmark.cursor(buf_id, {
             start_col = start_col,
             end_col = end_col,
-            line = states.selected,
+            line = buf_item.line,
         })
     else
         states.selected_ext_id = nil
     end
 end
 
-function M.render_results(buffers)
-    update_states(buffers)
+function M.render_results(sections)
+    update_states(sections)
 
     local lines = {}
     local exts = {}
 
-    lines, exts = render_items(buffers.current_tab, lines, exts, frame.titles[1])
-    lines, exts = render_items(buffers.other_tabs, lines, exts, frame.titles[2])
+    for _, section in ipairs(sections) do
+        lines, exts = render_items(section.items, lines, exts, frame_title(section.title))
+    end
 
     local buf_id = ui.main.get_buf()
     if not buf_id then return end
@@ -243,7 +278,7 @@ function M.render_results(buffers)
         end
     end
 
-    if #states.items.current_tab ~= 1 then
+    if states.first_section_len ~= 1 then
         local virt_line = {
             {
                 frame.vert,
@@ -260,7 +295,8 @@ function M.render_results(buffers)
         }
 
         local line = 0
-        if #states.items.current_tab > 1 and states.items.current_tab[1].matched then
+        if states.first_section_len > 1 and states.selected
+            and states.items[states.selected] == states.first_item then
             line = 1
         end
         hl.set_extmark.virt_lines(buf_id, { line = line, virt_line = virt_line })
@@ -269,15 +305,18 @@ function M.render_results(buffers)
     set_selected_hl(buf_id)
 end
 
-function M.open_results(buffers)
+function M.open_results(sections)
     ui.main.create_buf()
     local buf = ui.main.get_buf()
     if not buf then return end
     hl.clear_extmarks(buf)
     ui.main.set_lines(0, -1, false, {})
 
-    local height = #buffers.current_tab + #buffers.other_tabs + 4
-    if #buffers.current_tab ~= 1 then
+    local height = 0
+    for _, section in ipairs(sections) do
+        height = height + #section.items + 2
+    end
+    if not sections[1] or #sections[1].items ~= 1 then
         height = height + 1
     end
     ui.update_opts({ geom = { main = { height = height } } })
@@ -311,19 +350,42 @@ end
 function M.open_selected_buf()
     if not states.selected then return end
 
-    local buf_item = states.items.current_tab[states.selected]
+    local buf_item = states.items[states.selected]
     local buf_id = buf_item.buf
 
+    if buf_item.path and buf_item.path ~= "" then
+        rpc.call.selected(M.get_input(), buf_item.path)
+    end
+
     vim.cmd("stopinsert")
     myui.focus_on_last_active_win()
     myui.close_all()
-    api.nvim_set_current_buf(buf_id)
+    -- A buffer is opened in its preferred tab, which is another tab for a buffer of another tab
+    -- or for a deduplicated one preferring other tabs.
+    local tab = buf_item.tab
+    if tab and tab ~= api.nvim_get_current_tabpage() and api.nvim_tabpage_is_valid(tab) then
+        api.nvim_set_current_tabpage(tab)
+    end
+    -- A recently closed buffer may have been wiped out.
+    if api.nvim_buf_is_valid(buf_id) then
+        api.nvim_set_current_buf(buf_id)
+    elseif buf_item.path and buf_item.path ~= "" then
+        vim.cmd.edit(vim.fn.fnameescape(buf_item.path))
+    end
+end
+
+function M.explain_selected()
+    if not states.selected then return end
+
+    local buf_item = states.items[states.selected]
+    local explained = rpc.call.explain(M.get_input(), buf_item.buf)
+    vim.notify(vim.inspect(explained), vim.log.levels.INFO)
 end
 
 local function select_new_buf_item(find)
     local current_selected = states.selected
     if not current_selected then return end
-    states.selected = find(current_selected, states.items.current_tab)
+    states.selected = find(current_selected, states.items)
 
     local buf_id = ui.main.get_buf()
     if not buf_id then return end
@@ -367,6 +429,10 @@ end
 
 M.update_opts = ui.update_opts
 
+function M.configure(opts)
+    config = vim.tbl_extend("force", config, opts)
+end
+
 function M.autocmd()
     local augroup = api.nvim_create_augroup("NaughieBufferSwitcherUi", { clear = true }),
 